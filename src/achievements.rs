@@ -0,0 +1,204 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy_asset_loader::{AssetCollection, AssetCollectionApp};
+use bevy_egui_kbgp::bevy_egui::EguiContext;
+use bevy_egui_kbgp::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::game_systems::gameplay_system_set;
+use crate::global_types::{
+    GameplayStage, GameplaySystemLabel, InputSource, PlayerJumped, ScoreFormula, ScoreStatus,
+    TrunkChipped, WoodchipCleared,
+};
+use crate::loading::{RonAsset, RonAssetLoader};
+use crate::persistence;
+use crate::utils::some_or;
+
+pub struct AchievementsPlugin;
+
+impl Plugin for AchievementsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<AchievementDefinitions>();
+        app.init_asset_loader::<RonAssetLoader<AchievementDefinitions>>();
+        app.init_collection::<AchievementAssets>();
+        app.insert_resource(
+            persistence::load::<UnlockedAchievements>(STORAGE_KEY).unwrap_or_default(),
+        );
+        app.init_resource::<AchievementToasts>();
+        app.add_system_set_to_stage(GameplayStage, {
+            gameplay_system_set()
+                .with_system(unlock_achievements.after(GameplaySystemLabel::UpdateScore))
+        });
+        app.add_system(show_achievement_toasts);
+    }
+}
+
+const STORAGE_KEY: &str = "achievements";
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+#[derive(AssetCollection)]
+struct AchievementAssets {
+    #[asset(path = "game.achievements.ron")]
+    definitions: Handle<AchievementDefinitions>,
+}
+
+/// All the achievements in the game, loaded from a `.achievements.ron` file.
+#[derive(TypeUuid, Deserialize, Debug)]
+#[uuid = "9e1350bd-e1e6-4d72-8ede-b6bd4e1e7547"]
+pub struct AchievementDefinitions {
+    pub achievements: Vec<Achievement>,
+}
+
+impl RonAsset for AchievementDefinitions {
+    const EXTENSIONS: &'static [&'static str] = &["achievements.ron"];
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Achievement {
+    /// Used for remembering the unlock. Must not change once released.
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub condition: AchievementCondition,
+}
+
+/// Checked against the current run - for the whole team as well as for each player.
+#[derive(Deserialize, Debug)]
+pub enum AchievementCondition {
+    /// In seconds.
+    Survive(f32),
+    ChipLogs(u32),
+    ClearWoodchips(u32),
+    ClearWoodchipsWithoutTouchingGround(u32),
+    /// Number of wood chips cleared in quick succession.
+    Combo(u32),
+    Jumps(u32),
+    Score(u64),
+}
+
+impl AchievementCondition {
+    /// Whether the condition can become met without any gameplay event, just by the time passing.
+    fn depends_on_time(&self) -> bool {
+        match self {
+            AchievementCondition::Survive(_) | AchievementCondition::Score(_) => true,
+            AchievementCondition::ChipLogs(_)
+            | AchievementCondition::ClearWoodchips(_)
+            | AchievementCondition::ClearWoodchipsWithoutTouchingGround(_)
+            | AchievementCondition::Combo(_)
+            | AchievementCondition::Jumps(_) => false,
+        }
+    }
+
+    fn is_met(&self, score_status: &ScoreStatus, score_formula: &ScoreFormula) -> bool {
+        match self {
+            AchievementCondition::Survive(seconds) => *seconds <= score_status.time.as_secs_f32(),
+            AchievementCondition::ChipLogs(logs) => *logs <= score_status.logs_chipped,
+            AchievementCondition::ClearWoodchips(woodchips) => {
+                *woodchips <= score_status.woodchips_cleared
+            }
+            AchievementCondition::ClearWoodchipsWithoutTouchingGround(woodchips) => {
+                *woodchips <= score_status.woodchips_without_touching_ground
+            }
+            AchievementCondition::Combo(combo) => *combo <= score_status.combo,
+            AchievementCondition::Jumps(jumps) => *jumps <= score_status.jumps,
+            AchievementCondition::Score(score) => *score <= score_status.score(score_formula),
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct UnlockedAchievements {
+    pub ids: BTreeSet<String>,
+}
+
+#[derive(Default)]
+struct AchievementToasts(VecDeque<(String, Timer)>);
+
+fn unlock_achievements(
+    achievement_assets: Res<AchievementAssets>,
+    definitions: Res<Assets<AchievementDefinitions>>,
+    input_source: Res<InputSource>,
+    score_formula: Res<ScoreFormula>,
+    score_status: Res<ScoreStatus>,
+    players_query: Query<&ScoreStatus>,
+    mut trunk_chipped_reader: EventReader<TrunkChipped>,
+    mut woodchip_cleared_reader: EventReader<WoodchipCleared>,
+    mut player_jumped_reader: EventReader<PlayerJumped>,
+    mut unlocked: ResMut<UnlockedAchievements>,
+    mut toasts: ResMut<AchievementToasts>,
+) {
+    // Every count in the score comes from one of these events, and this runs in the same step
+    // they were sent in - before anything else could reset what they counted.
+    let any_chipped = trunk_chipped_reader.iter().count() != 0;
+    let mut players_with_events: Vec<Entity> = woodchip_cleared_reader
+        .iter()
+        .map(|event| event.player)
+        .chain(player_jumped_reader.iter().map(|event| event.player))
+        .collect();
+    players_with_events.sort();
+    players_with_events.dedup();
+    let team_has_events = any_chipped || !players_with_events.is_empty();
+
+    if *input_source == InputSource::Replay {
+        return;
+    }
+    let definitions = some_or!(definitions.get(&achievement_assets.definitions); return);
+    let mut any_unlocked = false;
+    for achievement in definitions.achievements.iter() {
+        if unlocked.ids.contains(&achievement.id) {
+            continue;
+        }
+        // Only the time of the whole team advances - the per-player statuses only change with
+        // their own events.
+        let is_met = ((team_has_events || achievement.condition.depends_on_time())
+            && achievement.condition.is_met(&score_status, &score_formula))
+            || players_with_events.iter().any(|player| {
+                players_query
+                    .get(*player)
+                    .map_or(false, |player_score_status| {
+                        achievement
+                            .condition
+                            .is_met(player_score_status, &score_formula)
+                    })
+            });
+        if !is_met {
+            continue;
+        }
+        info!("Achievement unlocked: {}", achievement.id);
+        unlocked.ids.insert(achievement.id.clone());
+        toasts.0.push_back((
+            format!("{}\n{}", achievement.name, achievement.description),
+            Timer::new(TOAST_DURATION, false),
+        ));
+        any_unlocked = true;
+    }
+    if any_unlocked {
+        persistence::save(STORAGE_KEY, &*unlocked);
+    }
+}
+
+fn show_achievement_toasts(
+    time: Res<Time>,
+    mut egui_context: ResMut<EguiContext>,
+    mut toasts: ResMut<AchievementToasts>,
+) {
+    // Show one toast at a time, so that unlocking several achievements at once does not flood
+    // the screen.
+    let (text, timer) = some_or!(toasts.0.front_mut(); return);
+    if timer.tick(time.delta()).finished() {
+        toasts.0.pop_front();
+        return;
+    }
+    egui::Area::new("achievement-toast")
+        .anchor(egui::Align2::RIGHT_TOP, [-10.0, 10.0])
+        .interactable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.colored_label(egui::Color32::YELLOW, "Achievement Unlocked!");
+                ui.label(text.as_str());
+            });
+        });
+}
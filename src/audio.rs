@@ -0,0 +1,194 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_kira_audio::{Audio, AudioChannel, AudioSource};
+
+use crate::global_types::{
+    AppState, Chipper, ChipperEffect, ChipperJammed, ChipperUnjammed, PlayerJumped, PlayerKilled,
+    TrunkChipped,
+};
+use crate::loading::AudioAssets;
+use crate::settings::Settings;
+
+pub struct GameAudioPlugin;
+
+impl Plugin for GameAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AudioChannels {
+            music: AudioChannel::new("music".to_owned()),
+            effects: AudioChannel::new("effects".to_owned()),
+        });
+        app.init_resource::<ChipperSounds>();
+        app.add_startup_system(start_music);
+        app.add_system(apply_audio_volumes);
+        app.add_system(play_sound_effects);
+        app.add_system(play_chipper_sounds);
+    }
+}
+
+struct AudioChannels {
+    music: AudioChannel,
+    effects: AudioChannel,
+}
+
+fn start_music(audio: Res<Audio>, audio_assets: Res<AudioAssets>, channels: Res<AudioChannels>) {
+    audio.play_looped_in_channel(audio_assets.music.clone(), &channels.music);
+}
+
+fn apply_audio_volumes(audio: Res<Audio>, settings: Res<Settings>, channels: Res<AudioChannels>) {
+    if !settings.is_changed() {
+        return;
+    }
+    audio.set_volume_in_channel(settings.music_volume, &channels.music);
+    audio.set_volume_in_channel(settings.effects_volume, &channels.effects);
+}
+
+fn play_sound_effects(
+    audio: Res<Audio>,
+    audio_assets: Res<AudioAssets>,
+    channels: Res<AudioChannels>,
+    mut jumped_reader: EventReader<PlayerJumped>,
+    mut chipped_reader: EventReader<TrunkChipped>,
+    mut jammed_reader: EventReader<ChipperJammed>,
+    mut unjammed_reader: EventReader<ChipperUnjammed>,
+    mut killed_reader: EventReader<PlayerKilled>,
+) {
+    let play = |sound: &Handle<AudioSource>, times: usize| {
+        // Several events of the same type in one frame would just sound like one louder effect
+        if 0 < times {
+            audio.play_in_channel(sound.clone(), &channels.effects);
+        }
+    };
+    play(&audio_assets.jump, jumped_reader.iter().count());
+    play(&audio_assets.chip, chipped_reader.iter().count());
+    play(&audio_assets.jam, jammed_reader.iter().count());
+    play(&audio_assets.unjam, unjammed_reader.iter().count());
+    play(&audio_assets.death, killed_reader.iter().count());
+}
+
+/// Chippers further than this angle from the center of the view are panned all the way to the
+/// side.
+const CHIPPER_FULL_PAN_ANGLE: f32 = std::f32::consts::FRAC_PI_4;
+const CHIPPING_WOOD_PLAYBACK_RATE: f32 = 0.8;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ChipperSoundLoop {
+    Saw,
+    Stall,
+}
+
+/// Each chipper gets its own channel, because panning and pitch can only be set per channel.
+struct ChipperSound {
+    channel: AudioChannel,
+    sound_loop: Option<ChipperSoundLoop>,
+    panning: f32,
+    playback_rate: f32,
+}
+
+#[derive(Default)]
+struct ChipperSounds {
+    sounds: HashMap<Entity, ChipperSound>,
+    /// Channels of despawned chippers. Kira keeps every channel it has seen, so they are reused
+    /// instead of creating new ones with every level.
+    free_channels: Vec<AudioChannel>,
+    next_channel_id: usize,
+}
+
+fn play_chipper_sounds(
+    audio: Res<Audio>,
+    audio_assets: Res<AudioAssets>,
+    settings: Res<Settings>,
+    state: Res<State<AppState>>,
+    cameras_query: Query<&GlobalTransform, With<PerspectiveProjection>>,
+    chippers_query: Query<(Entity, &Chipper, &ChipperEffect, &GlobalTransform)>,
+    mut chipper_sounds: ResMut<ChipperSounds>,
+) {
+    let ChipperSounds {
+        sounds,
+        free_channels,
+        next_channel_id,
+    } = &mut *chipper_sounds;
+
+    // Chippers that were despawned with the level
+    sounds.retain(|chipper_entity, sound| {
+        if chippers_query.get(*chipper_entity).is_ok() {
+            true
+        } else {
+            audio.stop_channel(&sound.channel);
+            free_channels.push(sound.channel.clone());
+            false
+        }
+    });
+
+    let world_to_camera = cameras_query
+        .iter()
+        .next()
+        .map(|camera_transform| camera_transform.compute_matrix().inverse());
+    let is_in_game = *state.current() == AppState::Game;
+
+    for (chipper_entity, chipper, chipper_effect, chipper_transform) in chippers_query.iter() {
+        let sound = sounds.entry(chipper_entity).or_insert_with(|| {
+            let channel = free_channels.pop().unwrap_or_else(|| {
+                let channel = AudioChannel::new(format!("chipper-{}", next_channel_id));
+                *next_channel_id += 1;
+                channel
+            });
+            // A reused channel still has the settings of its previous chipper
+            audio.set_volume_in_channel(settings.effects_volume, &channel);
+            audio.set_panning_in_channel(0.5, &channel);
+            audio.set_playback_rate_in_channel(1.0, &channel);
+            ChipperSound {
+                channel,
+                sound_loop: None,
+                panning: 0.5,
+                playback_rate: 1.0,
+            }
+        });
+        if settings.is_changed() {
+            audio.set_volume_in_channel(settings.effects_volume, &sound.channel);
+        }
+
+        // The level stays in the background of the pause and game over menus, but its chippers
+        // should not be heard there.
+        let sound_loop = if !is_in_game {
+            None
+        } else if matches!(chipper, Chipper::Jammed) {
+            Some(ChipperSoundLoop::Stall)
+        } else {
+            Some(ChipperSoundLoop::Saw)
+        };
+        if sound_loop != sound.sound_loop {
+            audio.stop_channel(&sound.channel);
+            match sound_loop {
+                None => {}
+                Some(ChipperSoundLoop::Saw) => {
+                    audio.play_looped_in_channel(audio_assets.saw.clone(), &sound.channel);
+                }
+                Some(ChipperSoundLoop::Stall) => {
+                    audio.play_looped_in_channel(audio_assets.stall.clone(), &sound.channel);
+                }
+            }
+            sound.sound_loop = sound_loop;
+        }
+
+        if let Some(world_to_camera) = world_to_camera {
+            let in_camera = world_to_camera.transform_point3(chipper_transform.translation);
+            // The camera looks along its negative Z axis
+            let angle = in_camera.x.atan2(-in_camera.z);
+            let panning = 0.5 + 0.5 * (angle / CHIPPER_FULL_PAN_ANGLE).clamp(-1.0, 1.0);
+            if 0.01 < (panning - sound.panning).abs() {
+                audio.set_panning_in_channel(panning, &sound.channel);
+                sound.panning = panning;
+            }
+        }
+
+        let playback_rate = if *chipper_effect == ChipperEffect::ChippingWood {
+            CHIPPING_WOOD_PLAYBACK_RATE
+        } else {
+            1.0
+        };
+        if playback_rate != sound.playback_rate {
+            audio.set_playback_rate_in_channel(playback_rate, &sound.channel);
+            sound.playback_rate = playback_rate;
+        }
+    }
+}
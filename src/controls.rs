@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::persistence;
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ControlBindings::load());
+    }
+}
+
+const STORAGE_KEY: &str = "controls";
+
+/// What the player can bind keys and buttons to. Moving left and moving right are both fed into
+/// `InputBinding::MoveHorizontal`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ControlAction {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Pause,
+}
+
+impl ControlAction {
+    pub const ALL: [ControlAction; 4] = [
+        ControlAction::MoveLeft,
+        ControlAction::MoveRight,
+        ControlAction::Jump,
+        ControlAction::Pause,
+    ];
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ControlAction::MoveLeft => "Move Left",
+            ControlAction::MoveRight => "Move Right",
+            ControlAction::Jump => "Jump",
+            ControlAction::Pause => "Pause",
+        }
+    }
+}
+
+/// The analog sticks are always bound to movement, and are not listed here.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlBindings {
+    pub keyboard: Vec<(ControlAction, KeyCode)>,
+    pub gamepad: Vec<(ControlAction, GamepadButtonType)>,
+}
+
+impl Default for ControlBindings {
+    fn default() -> Self {
+        Self {
+            keyboard: vec![
+                (ControlAction::MoveLeft, KeyCode::Left),
+                (ControlAction::MoveLeft, KeyCode::A),
+                (ControlAction::MoveRight, KeyCode::Right),
+                (ControlAction::MoveRight, KeyCode::D),
+                (ControlAction::Jump, KeyCode::Up),
+                (ControlAction::Jump, KeyCode::W),
+                (ControlAction::Pause, KeyCode::Escape),
+            ],
+            gamepad: vec![
+                (ControlAction::MoveLeft, GamepadButtonType::DPadLeft),
+                (ControlAction::MoveRight, GamepadButtonType::DPadRight),
+                (ControlAction::Jump, GamepadButtonType::South),
+                (ControlAction::Pause, GamepadButtonType::Start),
+            ],
+        }
+    }
+}
+
+impl ControlBindings {
+    pub fn load() -> Self {
+        persistence::load(STORAGE_KEY).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        persistence::save(STORAGE_KEY, self);
+    }
+}
+
+/// Replace all the bindings of the action with the new input. Fails with the action the input is
+/// already bound to, if it is bound to a different one.
+pub fn rebind<T: Copy + PartialEq>(
+    bindings: &mut Vec<(ControlAction, T)>,
+    action: ControlAction,
+    input: T,
+) -> Result<(), ControlAction> {
+    if let Some((other_action, _)) = bindings
+        .iter()
+        .find(|(other_action, other_input)| *other_input == input && *other_action != action)
+    {
+        return Err(*other_action);
+    }
+    bindings.retain(|(bound_action, _)| *bound_action != action);
+    bindings.push((action, input));
+    Ok(())
+}
@@ -1,5 +1,7 @@
 use bevy::prelude::*;
 
+use crate::settings::Settings;
+
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
@@ -8,7 +10,7 @@ impl Plugin for CameraPlugin {
     }
 }
 
-fn setup_camera(mut commands: Commands) {
+fn setup_camera(mut commands: Commands, settings: Res<Settings>) {
     let camera = PerspectiveCameraBundle {
         transform: Transform::from_xyz(8.0, 10.0, 30.0)
             .looking_at(Vec3::new(2.0, 0.0, 0.0), Vec3::Y),
@@ -20,7 +22,7 @@ fn setup_camera(mut commands: Commands) {
         directional_light: DirectionalLight {
             color: Color::WHITE,
             illuminance: 50_000.0,
-            shadows_enabled: true,
+            shadows_enabled: settings.shadows,
             ..Default::default()
         },
         transform: Transform::from_xyz(10.0, 10.0, 20.0)
@@ -0,0 +1,50 @@
+use bevy::prelude::*;
+
+use crate::game_systems::gameplay_system_set;
+use crate::global_types::{AppState, GameplayStage, MenuState, ScoreStatus};
+use crate::level::{Campaign, CampaignProgress, LevelSelection};
+use crate::loading::LevelAssets;
+use crate::utils::some_or;
+
+pub struct CampaignPlugin;
+
+impl Plugin for CampaignPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set_to_stage(GameplayStage, {
+            gameplay_system_set().with_system(complete_level_when_goal_is_reached)
+        });
+    }
+}
+
+fn complete_level_when_goal_is_reached(
+    level_selection: Res<LevelSelection>,
+    level_assets: Res<LevelAssets>,
+    campaigns: Res<Assets<Campaign>>,
+    score_status: Res<ScoreStatus>,
+    mut campaign_progress: ResMut<CampaignProgress>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let level_index = if let LevelSelection::Campaign(level_index) = *level_selection {
+        level_index
+    } else {
+        return;
+    };
+    let campaign = some_or!(campaigns.get(&level_assets.campaign); return);
+    let campaign_level = some_or!(campaign.levels.get(level_index); return);
+    if !campaign_level.goal.is_reached(&score_status) {
+        return;
+    }
+    let unlocked = campaign_progress
+        .unlocked
+        .max(level_index + 2)
+        .min(campaign.levels.len());
+    if campaign_progress.unlocked != unlocked {
+        campaign_progress.unlocked = unlocked;
+        campaign_progress.save();
+    }
+    // The gameplay stage may run more than once per frame - so the level completion may already be
+    // queued.
+    state
+        .overwrite_set(AppState::Menu(MenuState::LevelComplete))
+        .unwrap();
+}
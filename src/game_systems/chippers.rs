@@ -5,11 +5,18 @@ use bevy::utils::HashSet;
 use bevy_hanabi::ParticleEffect;
 use bevy_rapier2d::prelude::*;
 use bevy_tweening::lens::TransformRotateXLens;
-use bevy_tweening::{Animator, EaseMethod, Tween, TweeningType};
+use bevy_tweening::{Animator, AnimatorState, EaseMethod, Tween, TweeningType};
 
-use crate::global_types::{AppState, Chipper, DespawnWithLevel, ParticleEffectType, Trunk};
-use crate::gltf_spawner::{SpawnCollider, SpawnGltfNode};
+use crate::game_systems::gameplay_system_set;
+use crate::global_types::{
+    AppState, Chipper, ChipperEffect, ChipperJammed, ChipperUnjammed, DespawnWithLevel,
+    GameplayClock, GameplayStage, GameplaySystemLabel, JamDuration, ParticleEffectType,
+    ScoreFormula, ScoreStatus, Trunk,
+};
+use crate::gltf_spawner::{ColliderProperties, SpawnCollider, SpawnGltfNode};
+use crate::level::{CurrentLevel, Level};
 use crate::loading::ModelAssets;
+use crate::utils::{ok_or, some_or};
 
 pub struct ChippersPlugin;
 
@@ -17,11 +24,24 @@ impl Plugin for ChippersPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(SystemSet::on_enter(AppState::LoadLevel).with_system(setup_chippers));
         app.add_system_set(SystemSet::on_update(AppState::Game).with_system(set_chipper_effect));
+        app.add_system_set_to_stage(GameplayStage, {
+            gameplay_system_set()
+                .with_system(track_jam_duration.before(GameplaySystemLabel::UpdateScore))
+                .with_system(
+                    stop_saws_of_jammed_chippers.after(GameplaySystemLabel::SendGameplayEvents),
+                )
+        });
     }
 }
 
-fn setup_chippers(mut commands: Commands, model_assets: Res<ModelAssets>) {
-    for x in -3..=3 {
+fn setup_chippers(
+    mut commands: Commands,
+    model_assets: Res<ModelAssets>,
+    levels: Res<Assets<Level>>,
+    current_level: Res<CurrentLevel>,
+) {
+    let level = some_or!(levels.get(&current_level.0); return);
+    for &(x, y) in level.chippers.iter() {
         let mut cmd = commands.spawn();
         cmd.insert(Transform::identity());
         cmd.insert(GlobalTransform::identity());
@@ -60,33 +80,27 @@ fn setup_chippers(mut commands: Commands, model_assets: Res<ModelAssets>) {
         });
         cmd.insert_bundle(RigidBodyBundle {
             body_type: RigidBodyType::Static.into(),
-            position: point![x as f32 * 2.1, -1.0].into(),
+            position: point![x, y].into(),
             ..Default::default()
         });
         cmd.insert(RigidBodyPositionSync::Discrete);
         cmd.insert(SpawnCollider {
             gltf: model_assets.chipper.clone(),
             node_name: "Collider",
-            collider_type: ColliderType::Sensor,
-            material: Default::default(),
-            flags: ColliderFlags {
-                active_events: ActiveEvents::INTERSECTION_EVENTS,
+            properties: ColliderProperties {
+                sensor: Some(true),
+                intersection_events: Some(true),
                 ..Default::default()
             },
+            defaults: Default::default(),
         });
         cmd.insert(Chipper::Free);
+        cmd.insert(JamDuration::default());
         cmd.insert(ChipperEffect::NoEffect);
         cmd.insert(DespawnWithLevel);
     }
 }
 
-#[derive(Component, PartialEq)]
-enum ChipperEffect {
-    NoEffect,
-    ChippingWood,
-    Smoking,
-}
-
 fn set_chipper_effect(
     mut commands: Commands,
     trunks_query: Query<&Trunk>,
@@ -130,3 +144,55 @@ fn set_chipper_effect(
         }
     }
 }
+
+fn track_jam_duration(
+    clock: Res<GameplayClock>,
+    score_formula: Res<ScoreFormula>,
+    mut chippers_query: Query<(&Chipper, &mut JamDuration)>,
+    mut score_status: ResMut<ScoreStatus>,
+    mut players_query: Query<&mut ScoreStatus>,
+) {
+    let mut combo_lost = false;
+    for (chipper, mut jam_duration) in chippers_query.iter_mut() {
+        match chipper {
+            Chipper::Free => {
+                jam_duration.0 = Duration::ZERO;
+            }
+            Chipper::Jammed => {
+                jam_duration.0 += clock.step;
+                score_status.longest_jam = score_status.longest_jam.max(jam_duration.0);
+                if score_formula.combo_jam_limit <= jam_duration.0 {
+                    combo_lost = true;
+                }
+            }
+        }
+    }
+    if combo_lost {
+        score_status.combo = 0;
+        for mut player_score_status in players_query.iter_mut() {
+            player_score_status.combo = 0;
+        }
+    }
+}
+
+fn stop_saws_of_jammed_chippers(
+    mut jammed_reader: EventReader<ChipperJammed>,
+    mut unjammed_reader: EventReader<ChipperUnjammed>,
+    chippers_query: Query<&Children, With<Chipper>>,
+    mut saws_query: Query<&mut Animator<Transform>>,
+) {
+    let jammed = jammed_reader
+        .iter()
+        .map(|event| (event.chipper, AnimatorState::Paused));
+    let unjammed = unjammed_reader
+        .iter()
+        .map(|event| (event.chipper, AnimatorState::Playing));
+    for (chipper_entity, animator_state) in jammed.chain(unjammed) {
+        let chipper_children = ok_or!(chippers_query.get(chipper_entity); continue);
+        for saw_entity in chipper_children.iter() {
+            if let Ok(mut saw_animator) = saws_query.get_mut(*saw_entity) {
+                saw_animator.state = animator_state;
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+
+use crate::game_systems::gameplay_system_set;
+use crate::global_types::{Difficulty, GameplayStage, GameplaySystemLabel, ScoreStatus};
+use crate::level::{CurrentLevel, Level};
+use crate::utils::some_or;
+
+pub struct DifficultyPlugin;
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Difficulty>();
+        app.add_system_set_to_stage(GameplayStage, {
+            gameplay_system_set()
+                .with_system(update_difficulty.label(GameplaySystemLabel::UpdateDifficulty))
+        });
+    }
+}
+
+fn update_difficulty(
+    score_status: Res<ScoreStatus>,
+    levels: Res<Assets<Level>>,
+    current_level: Res<CurrentLevel>,
+    mut difficulty: ResMut<Difficulty>,
+) {
+    let level = some_or!(levels.get(&current_level.0); return);
+    let ramp = if let Some(ramp) = &level.difficulty_ramp {
+        ramp
+    } else {
+        *difficulty = Default::default();
+        return;
+    };
+    let difficulty_level = (score_status.time.as_secs_f32() / ramp.seconds_per_level
+        + score_status.logs_chipped as f32 / ramp.logs_per_level)
+        .min(ramp.max_level);
+    *difficulty = Difficulty {
+        level: difficulty_level,
+        extra_trunks: (difficulty_level * ramp.extra_trunks_per_level) as usize,
+        extra_trunks_in_flight: (difficulty_level * ramp.extra_trunks_in_flight_per_level) as usize,
+        woodchip_interval_factor: ramp
+            .woodchip_interval_factor_per_level
+            .powf(difficulty_level),
+        launch_spread_factor: 1.0 + difficulty_level * ramp.launch_spread_per_level,
+    };
+}
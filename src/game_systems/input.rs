@@ -1,83 +1,102 @@
 use bevy::prelude::*;
 use ezinput::prelude::*;
 
-use crate::global_types::InputBinding;
+use crate::controls::{ControlAction, ControlBindings};
+use crate::game_systems::gameplay_system_set;
+use crate::global_types::{
+    AppState, GameMode, GameplayStage, GameplaySystemLabel, InputBinding, InputSource,
+    JoinPlayerEvent, MenuState, PlayerControlledBy, PlayerInput, TouchControlsInput,
+};
 
 pub struct InputPlugin;
 
 impl Plugin for InputPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(EZInputPlugin::<InputBinding>::default());
-        app.init_resource::<InputConfig>();
+        app.init_resource::<ControlBindings>();
+        app.init_resource::<GameMode>();
+        app.init_resource::<InputSource>();
+        app.init_resource::<TouchControlsInput>();
+        app.add_event::<JoinPlayerEvent>();
         app.add_startup_system(setup_keyboard_input);
         app.add_system(handle_gamepad_events);
+        app.add_system(apply_control_bindings);
+        app.add_system(read_player_input);
+        app.add_system_set_to_stage(GameplayStage, {
+            gameplay_system_set().with_system(join_players.label(GameplaySystemLabel::JoinPlayers))
+        });
+        app.add_system(pause_unpause_game);
     }
 }
 
-struct InputConfig(InputView<InputBinding>);
-
-impl Default for InputConfig {
-    fn default() -> Self {
-        let mut view = InputView::empty();
+fn create_input_view(bindings: &ControlBindings) -> InputView<InputBinding> {
+    let mut view = InputView::empty();
 
-        let mut binding = ActionBinding::from(InputBinding::MoveHorizontal);
-        for (input, axis_value) in [
-            (BindingInputReceiver::KeyboardKey(KeyCode::Left), -1.0),
-            (BindingInputReceiver::KeyboardKey(KeyCode::A), -1.0),
-            (BindingInputReceiver::KeyboardKey(KeyCode::Right), 1.0),
-            (BindingInputReceiver::KeyboardKey(KeyCode::D), 1.0),
-            (
-                BindingInputReceiver::GamepadButton(GamepadButtonType::DPadLeft),
-                -1.0,
-            ),
-            (
-                BindingInputReceiver::GamepadButton(GamepadButtonType::DPadRight),
-                1.0,
-            ),
-        ] {
-            binding
-                .receiver(input)
-                .default_axis_value(input, axis_value);
+    let mut move_binding = ActionBinding::from(InputBinding::MoveHorizontal);
+    let keyboard_inputs = bindings
+        .keyboard
+        .iter()
+        .map(|(action, key)| (*action, BindingInputReceiver::KeyboardKey(*key)));
+    let gamepad_inputs = bindings
+        .gamepad
+        .iter()
+        .map(|(action, button)| (*action, BindingInputReceiver::GamepadButton(*button)));
+    let mut jump_binding = ActionBinding::from(InputBinding::Jump);
+    let mut pause_binding = ActionBinding::from(InputBinding::Pause);
+    for (action, input) in keyboard_inputs.chain(gamepad_inputs) {
+        match action {
+            ControlAction::MoveLeft => {
+                move_binding.receiver(input).default_axis_value(input, -1.0);
+            }
+            ControlAction::MoveRight => {
+                move_binding.receiver(input).default_axis_value(input, 1.0);
+            }
+            ControlAction::Jump => {
+                jump_binding.receiver(input);
+            }
+            ControlAction::Pause => {
+                pause_binding.receiver(input);
+            }
         }
-        binding.receiver(BindingInputReceiver::GamepadAxis(
-            GamepadAxisType::LeftStickX,
-        ));
-        binding.receiver(BindingInputReceiver::GamepadAxis(GamepadAxisType::DPadX));
-        view.add_binding(&binding);
-
-        view.add_binding({
-            ActionBinding::from(InputBinding::Jump)
-                .receiver(BindingInputReceiver::KeyboardKey(KeyCode::Up))
-                .receiver(BindingInputReceiver::KeyboardKey(KeyCode::W))
-                .receiver(BindingInputReceiver::GamepadButton(
-                    GamepadButtonType::South,
-                ))
-        });
-
-        view.add_binding({
-            ActionBinding::from(InputBinding::Pause)
-                .receiver(BindingInputReceiver::KeyboardKey(KeyCode::Escape))
-                .receiver(BindingInputReceiver::GamepadButton(
-                    GamepadButtonType::Start,
-                ))
-        });
-
-        Self(view)
     }
+    move_binding.receiver(BindingInputReceiver::GamepadAxis(
+        GamepadAxisType::LeftStickX,
+    ));
+    move_binding.receiver(BindingInputReceiver::GamepadAxis(GamepadAxisType::DPadX));
+    view.add_binding(&move_binding);
+    view.add_binding(&jump_binding);
+    view.add_binding(&pause_binding);
+
+    view
 }
 
-fn setup_keyboard_input(mut commands: Commands, input_config: Res<InputConfig>) {
+fn setup_keyboard_input(mut commands: Commands, bindings: Res<ControlBindings>) {
     commands
         .spawn()
-        .insert(input_config.0.clone())
+        .insert(create_input_view(&bindings))
         .insert(EZInputKeyboardService);
 }
 
+fn apply_control_bindings(
+    bindings: Res<ControlBindings>,
+    input_views: Query<Entity, With<InputView<InputBinding>>>,
+    mut commands: Commands,
+) {
+    // The views created on startup already use them
+    if !bindings.is_changed() || bindings.is_added() {
+        return;
+    }
+    let view = create_input_view(&bindings);
+    for entity in input_views.iter() {
+        commands.entity(entity).insert(view.clone());
+    }
+}
+
 fn handle_gamepad_events(
     mut reader: EventReader<GamepadEvent>,
     gamepad_services: Query<(Entity, &EZInputGamepadService), With<InputView<InputBinding>>>,
     mut commands: Commands,
-    input_config: Res<InputConfig>,
+    bindings: Res<ControlBindings>,
 ) {
     for GamepadEvent(gamepad, event_type) in reader.iter() {
         match event_type {
@@ -88,7 +107,7 @@ fn handle_gamepad_events(
                 {
                     commands
                         .spawn()
-                        .insert(input_config.0.clone())
+                        .insert(create_input_view(&bindings))
                         .insert(EZInputGamepadService(*gamepad));
                 }
             }
@@ -103,3 +122,122 @@ fn handle_gamepad_events(
         }
     }
 }
+
+fn read_player_input(
+    input_source: Res<InputSource>,
+    input_views: Query<(Entity, &InputView<InputBinding>)>,
+    touch_controls_input: Res<TouchControlsInput>,
+    mut players_query: Query<(&PlayerControlledBy, &mut PlayerInput)>,
+) {
+    if *input_source != InputSource::Live {
+        return;
+    }
+    for (controlled_by, mut player_input) in players_query.iter_mut() {
+        *player_input = match controlled_by {
+            PlayerControlledBy::AnyInput => {
+                let mut input =
+                    input_from_views(input_views.iter().map(|(_, input_view)| input_view));
+                if touch_controls_input.move_horizontal != 0.0 {
+                    input.move_horizontal = touch_controls_input.move_horizontal;
+                }
+                input.jump |= touch_controls_input.jump;
+                input
+            }
+            PlayerControlledBy::InputView(view_entity) => input_from_views(
+                input_views
+                    .get(*view_entity)
+                    .map(|(_, input_view)| input_view)
+                    .into_iter(),
+            ),
+            PlayerControlledBy::Replay => continue,
+        };
+    }
+}
+
+fn input_from_views<'a>(
+    input_views: impl Iterator<Item = &'a InputView<InputBinding>>,
+) -> PlayerInput {
+    let mut movement_value = 0.0;
+    let mut num_participating = 0;
+    let mut is_jumping = false;
+    let mut is_pausing = false;
+    for input_view in input_views {
+        for axis_value in input_view.axis(&InputBinding::MoveHorizontal) {
+            if !axis_value.1.released() {
+                num_participating += 1;
+                movement_value = axis_value.0
+            }
+        }
+        if is_jump_pressed(input_view) {
+            is_jumping = true;
+        }
+        if input_view.key(&InputBinding::Pause).just_pressed() {
+            is_pausing = true;
+        }
+    }
+    PlayerInput {
+        move_horizontal: if 0 < num_participating {
+            movement_value / num_participating as f32
+        } else {
+            0.0
+        },
+        jump: is_jumping,
+        pause: is_pausing,
+    }
+}
+
+fn is_jump_pressed(input_view: &InputView<InputBinding>) -> bool {
+    matches!(
+        input_view.key(&InputBinding::Jump),
+        PressState::Pressed { .. }
+    )
+}
+
+fn join_players(
+    game_mode: Res<GameMode>,
+    input_source: Res<InputSource>,
+    input_views: Query<(Entity, &InputView<InputBinding>)>,
+    players_query: Query<&PlayerControlledBy>,
+    mut join_writer: EventWriter<JoinPlayerEvent>,
+) {
+    if !game_mode.players_join_by_jumping() || *input_source != InputSource::Live {
+        return;
+    }
+    for (view_entity, input_view) in input_views.iter() {
+        let controlled_by = PlayerControlledBy::InputView(view_entity);
+        if is_jump_pressed(input_view)
+            && !players_query
+                .iter()
+                .any(|player_controlled_by| *player_controlled_by == controlled_by)
+        {
+            join_writer.send(JoinPlayerEvent(controlled_by));
+        }
+    }
+}
+
+fn pause_unpause_game(
+    input_source: Res<InputSource>,
+    input_views: Query<&InputView<InputBinding>>,
+    mut state: ResMut<State<AppState>>,
+) {
+    // A replay pauses and resumes at the recorded steps
+    if *input_source == InputSource::Replay {
+        return;
+    }
+    if input_views
+        .iter()
+        .any(|view| view.key(&InputBinding::Pause).just_pressed())
+    {
+        match state.current() {
+            AppState::Menu(MenuState::Pause) => {
+                state.set(AppState::Game).unwrap();
+            }
+            AppState::Menu(_) => {}
+            AppState::ClearLevelAndThenLoad => {}
+            AppState::LoadLevel => {}
+            AppState::Game => {
+                state.set(AppState::Menu(MenuState::Pause)).unwrap();
+            }
+        }
+    }
+}
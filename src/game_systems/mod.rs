@@ -1,19 +1,41 @@
 mod arena;
 mod camera;
+mod campaign;
 mod chippers;
+mod difficulty;
 mod input;
 mod particle_effects;
 mod player;
+mod scoring;
 mod trunks;
 mod woodchips;
 
 use bevy::ecs::schedule::ShouldRun;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use bevy::transform::TransformSystem;
 
-use crate::global_types::{AppState, DespawnWithLevel, ScoreStatus};
+use bevy_rapier2d::physics::{
+    step_world_system, IntegrationParameters, NoUserData, RapierConfiguration,
+};
+use bevy_rapier2d::prelude::*;
+
+use crate::global_types::{
+    AppState, ChipperJammed, ChipperUnjammed, DespawnWithLevel, Difficulty, GameRng, GameplayClock,
+    GameplayStage, GameplaySystemLabel, InterpolatedPosition, MenuState, PlayerControl,
+    PlayerJumped, PlayerKilled, ScoreFormula, ScoreStatus, SeedOverride, TrunkChipped,
+    TrunkEnteredChipper, TrunkLost, VersusWinner, WoodchipCleared,
+};
+use crate::gltf_spawner::{resolve_collider_events, SpawnCollider};
+use crate::level::{Campaign, CampaignProgress, CurrentLevel, Level, LevelSelection};
+use crate::loading::LevelAssets;
+use crate::utils::some_or;
 
 pub struct GameSystemsPlugin;
 
+/// The parts of the game systems that only make sense when there is something to render to.
+pub struct GamePresentationPlugin;
+
 fn create_move_to_state_system(new_state: AppState) -> impl Fn(ResMut<State<AppState>>) {
     move |mut state: ResMut<State<AppState>>| {
         state.set(new_state.clone()).unwrap();
@@ -22,30 +44,141 @@ fn create_move_to_state_system(new_state: AppState) -> impl Fn(ResMut<State<AppS
 
 impl Plugin for GameSystemsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(camera::CameraPlugin);
+        app.insert_resource(ScoreStatus::default());
+        app.init_resource::<ScoreFormula>();
+        app.init_resource::<VersusWinner>();
+        app.init_resource::<LevelSelection>();
+        app.insert_resource(CampaignProgress::load());
+        app.init_resource::<CurrentLevel>();
+        app.init_resource::<SeedOverride>();
+        app.init_resource::<GameRng>();
+        app.init_resource::<GameplayClock>();
+        app.add_event::<TrunkEnteredChipper>();
+        app.add_event::<TrunkChipped>();
+        app.add_event::<TrunkLost>();
+        app.add_event::<ChipperJammed>();
+        app.add_event::<ChipperUnjammed>();
+        app.add_event::<WoodchipCleared>();
+        app.add_event::<PlayerJumped>();
+        app.add_event::<PlayerKilled>();
+        app.add_stage_after(
+            CoreStage::Update,
+            GameplayStage,
+            SystemStage::parallel().with_run_criteria(run_gameplay_step),
+        );
+        app.add_system_to_stage(CoreStage::PreUpdate, sync_physics_with_gameplay_clock);
         app.add_plugin(input::InputPlugin);
         app.add_plugin(arena::ArenaPlugin);
         app.add_plugin(player::PlayerPlugin);
         app.add_plugin(trunks::TrunksPlugin);
         app.add_plugin(chippers::ChippersPlugin);
         app.add_plugin(woodchips::WoodshipsPlugin);
-        app.add_plugin(particle_effects::ParticleEffectPlugin);
+        app.add_plugin(campaign::CampaignPlugin);
+        app.add_plugin(difficulty::DifficultyPlugin);
+        app.add_plugin(scoring::ScoringPlugin);
         app.add_system_set({
             SystemSet::on_enter(AppState::ClearLevelAndThenLoad)
                 .with_system(reset_score)
+                .with_system(reseed_rng)
                 .with_system(clear_and_load)
-                .with_system(create_move_to_state_system(AppState::LoadLevel))
+        });
+        app.add_system_set({
+            SystemSet::on_update(AppState::ClearLevelAndThenLoad)
+                .with_system(move_to_load_level_when_level_is_ready)
         });
         app.add_system_set({
             SystemSet::on_enter(AppState::LoadLevel)
                 .with_system(create_move_to_state_system(AppState::Game))
         });
+        app.add_system_set_to_stage(GameplayStage, {
+            gameplay_system_set().with_system(update_time)
+        });
+        // Rapier steps the physics in `CoreStage::Update`, once per frame. To keep the physics
+        // steps and the gameplay steps alternating no matter how many gameplay steps a frame has,
+        // the physics pipeline is only active while it is stepped inside the gameplay stage.
+        app.add_system_set_to_stage(GameplayStage, {
+            SystemSet::new()
+                .with_run_criteria(run_if_in_game)
+                .label(GameplaySystemLabel::StepPhysics)
+                .with_system(activate_physics_pipeline.before(PhysicsStepLabel::Step))
+                .with_system(store_previous_positions.before(PhysicsStepLabel::Step))
+                .with_system(step_world_system::<NoUserData>.label(PhysicsStepLabel::Step))
+                .with_system(deactivate_physics_pipeline.after(PhysicsStepLabel::Step))
+                .with_system(resolve_collider_events.after(PhysicsStepLabel::Step))
+        });
         app.add_system(enable_disable_physics.with_run_criteria(run_on_state_change));
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            interpolate_positions.before(TransformSystem::TransformPropagate),
+        );
     }
 }
 
-fn reset_score(mut score_status: ResMut<ScoreStatus>) {
+impl Plugin for GamePresentationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(camera::CameraPlugin);
+        app.add_plugin(particle_effects::ParticleEffectPlugin);
+    }
+}
+
+fn reset_score(
+    mut score_status: ResMut<ScoreStatus>,
+    mut versus_winner: ResMut<VersusWinner>,
+    mut difficulty: ResMut<Difficulty>,
+) {
     *score_status = Default::default();
+    *versus_winner = Default::default();
+    *difficulty = Default::default();
+}
+
+fn reseed_rng(seed_override: Res<SeedOverride>, mut game_rng: ResMut<GameRng>) {
+    let seed = seed_override.0.unwrap_or_else(rand::random);
+    info!("Loading level with seed {}", seed);
+    *game_rng = GameRng::new(seed);
+}
+
+fn move_to_load_level_when_level_is_ready(
+    level_selection: Res<LevelSelection>,
+    level_assets: Res<LevelAssets>,
+    campaigns: Res<Assets<Campaign>>,
+    levels: Res<Assets<Level>>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let level = match *level_selection {
+        LevelSelection::Endless => &level_assets.endless,
+        LevelSelection::Campaign(index) => {
+            let campaign = some_or!(campaigns.get(&level_assets.campaign); return);
+            if let Some(campaign_level) = campaign.levels.get(index) {
+                &campaign_level.level
+            } else {
+                // Can come from a replay file
+                error!(
+                    "The campaign has no level {} - it only has {} levels",
+                    index,
+                    campaign.levels.len()
+                );
+                state.set(AppState::Menu(MenuState::Main)).unwrap();
+                return;
+            }
+        }
+    };
+    // The level setup systems run when entering `LoadLevel`, so they need the level data
+    if levels.contains(level) {
+        current_level.0 = level.clone();
+        state.set(AppState::LoadLevel).unwrap();
+    }
+}
+
+fn update_time(
+    clock: Res<GameplayClock>,
+    mut score_status: ResMut<ScoreStatus>,
+    players_query: Query<(), With<PlayerControl>>,
+) {
+    if players_query.iter().next().is_none() {
+        return;
+    }
+    score_status.time += clock.step;
 }
 
 fn clear_and_load(
@@ -69,14 +202,155 @@ pub fn run_on_state_change(
     ShouldRun::Yes
 }
 
+fn run_gameplay_step(
+    time: Res<Time>,
+    mut clock: ResMut<GameplayClock>,
+    mut looping: Local<bool>,
+    pending_physics_changes: PendingPhysicsChanges,
+) -> ShouldRun {
+    if !*looping {
+        clock.start_frame(time.delta());
+    }
+    // Rapier finishes creating and removing bodies and colliders once per frame, outside the
+    // gameplay stage. Waiting for it makes every step see the same physics world, no matter how
+    // many steps the frame has.
+    *looping = !pending_physics_changes.any() && clock.take_step();
+    if *looping {
+        ShouldRun::YesAndCheckAgain
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Bodies and colliders that were spawned or despawned, but that Rapier did not finish adding to
+/// or removing from the physics world yet.
+#[derive(SystemParam)]
+struct PendingPhysicsChanges<'w, 's> {
+    colliders_to_spawn: Query<'w, 's, (), With<SpawnCollider>>,
+    unattached_colliders: Query<
+        'w,
+        's,
+        (),
+        (
+            With<ColliderShapeComponent>,
+            With<RigidBodyTypeComponent>,
+            Without<ColliderParentComponent>,
+        ),
+    >,
+    attached_colliders: Query<'w, 's, (Entity, &'static ColliderParentComponent)>,
+    bodies: Query<'w, 's, &'static RigidBodyCollidersComponent>,
+    removed_bodies: RemovedComponents<'w, RigidBodyPositionComponent>,
+    removed_colliders: RemovedComponents<'w, ColliderShapeComponent>,
+}
+
+impl<'w, 's> PendingPhysicsChanges<'w, 's> {
+    fn any(&self) -> bool {
+        self.colliders_to_spawn.iter().next().is_some()
+            || self.unattached_colliders.iter().next().is_some()
+            || self.removed_bodies.iter().next().is_some()
+            || self.removed_colliders.iter().next().is_some()
+            || self
+                .attached_colliders
+                .iter()
+                .any(|(collider_entity, collider_parent)| {
+                    // Despawned bodies are covered by `removed_bodies`
+                    self.bodies
+                        .get(collider_parent.handle.entity())
+                        .map_or(false, |body_colliders| {
+                            !body_colliders.0 .0.contains(&collider_entity.handle())
+                        })
+                })
+    }
+}
+
+fn run_if_in_game(state: Res<State<AppState>>) -> ShouldRun {
+    if *state.current() == AppState::Game {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// A system set for the [`GameplayStage`] that only runs while the game is not paused.
+pub(crate) fn gameplay_system_set() -> SystemSet {
+    SystemSet::new()
+        .with_run_criteria(run_if_in_game)
+        .after(GameplaySystemLabel::StepPhysics)
+}
+
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+enum PhysicsStepLabel {
+    Step,
+}
+
+fn sync_physics_with_gameplay_clock(
+    clock: Res<GameplayClock>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+    mut integration_parameters: ResMut<IntegrationParameters>,
+) {
+    if !clock.is_changed() {
+        return;
+    }
+    integration_parameters.dt = clock.step.as_secs_f32();
+    // The gameplay stage decides how many steps to run, so every call should be a single step
+    rapier_configuration.time_dependent_number_of_timesteps = false;
+}
+
+fn activate_physics_pipeline(mut rapier_configuration: ResMut<RapierConfiguration>) {
+    rapier_configuration.physics_pipeline_active = true;
+}
+
+fn deactivate_physics_pipeline(mut rapier_configuration: ResMut<RapierConfiguration>) {
+    rapier_configuration.physics_pipeline_active = false;
+}
+
+fn store_previous_positions(
+    mut query: Query<(&RigidBodyPositionComponent, &mut InterpolatedPosition)>,
+) {
+    for (rigid_body_position, mut interpolated_position) in query.iter_mut() {
+        interpolated_position.previous = Some(rigid_body_position.position);
+    }
+}
+
+fn interpolate_positions(
+    state: Res<State<AppState>>,
+    clock: Res<GameplayClock>,
+    rapier_configuration: Res<RapierConfiguration>,
+    mut query: Query<(
+        &RigidBodyPositionComponent,
+        &mut InterpolatedPosition,
+        &mut Transform,
+    )>,
+) {
+    let in_game = *state.current() == AppState::Game;
+    let step_fraction = clock.step_fraction();
+    for (rigid_body_position, mut interpolated_position, mut transform) in query.iter_mut() {
+        if !in_game {
+            // The clock keeps running while the game is paused, but the bodies don't move - so
+            // there is nothing to interpolate until the next step.
+            interpolated_position.previous = None;
+        }
+        let position = if let Some(previous) = interpolated_position.previous {
+            previous.lerp_slerp(&rigid_body_position.position, step_fraction)
+        } else {
+            rigid_body_position.position
+        };
+        let scale = rapier_configuration.scale;
+        transform.translation.x = position.translation.x * scale;
+        transform.translation.y = position.translation.y * scale;
+        transform.rotation = Quat::from_rotation_z(position.rotation.angle());
+    }
+}
+
 fn enable_disable_physics(
     state: Res<State<AppState>>,
-    mut rapier_configuration: ResMut<bevy_rapier2d::physics::RapierConfiguration>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
 ) {
     let set_to = match state.current() {
         AppState::Game => true,
         AppState::Menu(_) | AppState::ClearLevelAndThenLoad | AppState::LoadLevel => false,
     };
-    rapier_configuration.physics_pipeline_active = set_to;
+    // The physics pipeline is activated by the gameplay stage, only for its own steps
+    rapier_configuration.physics_pipeline_active = false;
     rapier_configuration.query_pipeline_active = set_to;
 }
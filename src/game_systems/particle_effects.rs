@@ -5,7 +5,7 @@ use bevy_hanabi::{
     Spawner,
 };
 
-use crate::global_types::ParticleEffectType;
+use crate::global_types::{ParticleEffectType, PlayerKilled};
 
 pub struct ParticleEffectPlugin;
 
@@ -13,6 +13,7 @@ impl Plugin for ParticleEffectPlugin {
     fn build(&self, app: &mut App) {
         app.add_system(handle_entities_who_need_particle_effects);
         app.add_system(move_particle_effects_to_targets);
+        app.add_system(bleed_killed_players);
     }
 }
 
@@ -203,3 +204,18 @@ fn create_effect(effect: ParticleEffectType) -> EffectAsset {
         }),
     }
 }
+
+fn bleed_killed_players(
+    mut commands: Commands,
+    mut reader: EventReader<PlayerKilled>,
+    players_query: Query<()>,
+) {
+    for event in reader.iter() {
+        // The level may have been cleared since the player was killed
+        if players_query.get(event.player).is_ok() {
+            commands
+                .entity(event.player)
+                .insert(ParticleEffectType::Blood);
+        }
+    }
+}
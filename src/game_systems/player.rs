@@ -4,12 +4,19 @@ use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use bevy_tweening::lens::TransformRotateYLens;
 use bevy_tweening::{Animator, AnimatorState, EaseFunction, Lens, Tween, TweeningType};
-use ezinput::prelude::*;
 
+use crate::game_systems::gameplay_system_set;
 use crate::global_types::{
-    AppState, Chipper, DespawnWithLevel, InputBinding, MenuState, ParticleEffectType, PlayerControl,
+    AppState, CauseOfDeath, Chipper, DespawnWithLevel, GameMode, GameplayClock, GameplayStage,
+    GameplaySystemLabel, InterpolatedPosition, JoinPlayerEvent, MenuState, PlayerControl,
+    PlayerControlledBy, PlayerIndex, PlayerInput, PlayerJumped, PlayerKilled, ScoreStatus,
+    VersusWinner, Woodchip,
 };
-use crate::gltf_spawner::{GltfNodeAddedEvent, SpawnCollider, SpawnGltfNode};
+use crate::gltf_spawner::{
+    collider_body_entity, BodyContactEvent, BodyIntersectionEvent, ColliderProperties,
+    GltfNodeAddedEvent, SpawnCollider, SpawnGltfNode,
+};
+use crate::level::{CurrentLevel, Level};
 use crate::loading::ModelAssets;
 use crate::utils::{entities_ordered_by_type, ok_or, some_or};
 
@@ -18,13 +25,25 @@ pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(SystemSet::on_enter(AppState::LoadLevel).with_system(setup_player));
+        app.add_system_set_to_stage(GameplayStage, {
+            gameplay_system_set()
+                .with_system(spawn_joined_players.after(GameplaySystemLabel::JoinPlayers))
+                .with_system(
+                    player_control
+                        .label(GameplaySystemLabel::SendGameplayEvents)
+                        .after(GameplaySystemLabel::OverrideInput),
+                )
+                .with_system(bump_players)
+                .with_system(kill_player.label(GameplaySystemLabel::SendGameplayEvents))
+                .with_system(update_survival_time)
+                .with_system(track_touching_ground.before(GameplaySystemLabel::UpdateScore))
+                .with_system(game_over_when_all_players_are_out)
+        });
         app.add_system_set({
             SystemSet::on_update(AppState::Game)
-                .with_system(player_control)
                 .with_system(add_animation)
+                .with_system(tint_player_parts)
                 .with_system(player_animation)
-                .with_system(kill_player)
-                .with_system(game_over_when_player_falls_too_much)
         });
     }
 }
@@ -39,7 +58,70 @@ struct PlayerStatusForAnimation {
     leg_entities: [Entity; 2],
 }
 
-fn setup_player(mut commands: Commands, model_assets: Res<ModelAssets>) {
+/// Multiplied into the materials of the player parts, so that co-op players can tell themselves
+/// apart. The first player keeps the original colors.
+const PLAYER_TINTS: [Color; 4] = [Color::CYAN, Color::PINK, Color::LIME_GREEN, Color::ORANGE];
+
+#[derive(Component)]
+struct PlayerPartTint(Color);
+
+fn setup_player(
+    mut commands: Commands,
+    model_assets: Res<ModelAssets>,
+    levels: Res<Assets<Level>>,
+    current_level: Res<CurrentLevel>,
+    game_mode: Res<GameMode>,
+) {
+    let level = some_or!(levels.get(&current_level.0); return);
+    match *game_mode {
+        GameMode::SinglePlayer => {
+            spawn_player(
+                &mut commands,
+                &model_assets,
+                level,
+                PlayerIndex(0),
+                PlayerControlledBy::AnyInput,
+            );
+        }
+        // Co-op and versus players join by pressing Jump
+        GameMode::CoOp | GameMode::Versus => {}
+    }
+}
+
+fn spawn_joined_players(
+    mut commands: Commands,
+    model_assets: Res<ModelAssets>,
+    levels: Res<Assets<Level>>,
+    current_level: Res<CurrentLevel>,
+    mut join_reader: EventReader<JoinPlayerEvent>,
+    players_query: Query<&PlayerIndex>,
+) {
+    let level = some_or!(levels.get(&current_level.0); return);
+    let mut next_index = players_query.iter().count();
+    for JoinPlayerEvent(controlled_by) in join_reader.iter() {
+        spawn_player(
+            &mut commands,
+            &model_assets,
+            level,
+            PlayerIndex(next_index),
+            *controlled_by,
+        );
+        next_index += 1;
+    }
+}
+
+fn spawn_player(
+    commands: &mut Commands,
+    model_assets: &ModelAssets,
+    level: &Level,
+    index: PlayerIndex,
+    controlled_by: PlayerControlledBy,
+) {
+    let tint = index
+        .0
+        .checked_sub(1)
+        .map(|tint_index| PLAYER_TINTS[tint_index % PLAYER_TINTS.len()]);
+    let (spawn_x, spawn_y) = level.player_spawn(index.0);
     let mut cmd = commands.spawn();
     cmd.insert_bundle(RigidBodyBundle {
         body_type: RigidBodyType::Dynamic.into(),
@@ -53,7 +135,7 @@ fn setup_player(mut commands: Commands, model_assets: Res<ModelAssets>) {
             ..Default::default()
         }
         .into(),
-        position: point![-3.0, 12.0].into(),
+        position: point![spawn_x, spawn_y].into(),
         // damping: RigidBodyDamping {
         // linear_damping: 1.0,
         // angular_damping: 0.0,
@@ -61,20 +143,19 @@ fn setup_player(mut commands: Commands, model_assets: Res<ModelAssets>) {
         // .into(),
         ..Default::default()
     });
-    cmd.insert(RigidBodyPositionSync::Discrete);
+    cmd.insert(InterpolatedPosition::default());
     cmd.insert(SpawnCollider {
         gltf: model_assets.player.clone(),
         node_name: "Collider",
-        collider_type: ColliderType::Solid,
-        material: ColliderMaterial {
-            friction: 4.0,
-            // restitution: todo!(),
-            // friction_combine_rule: todo!(),
-            // restitution_combine_rule: todo!(),
+        properties: ColliderProperties {
+            // For unjamming chippers and bumping other players
+            contact_events: Some(true),
+            // The mass is set on the rigid body, so that it stays the same when the model changes
+            density: Some(0.0),
             ..Default::default()
         },
-        flags: ColliderFlags {
-            active_events: ActiveEvents::CONTACT_EVENTS,
+        defaults: ColliderProperties {
+            friction: Some(4.0),
             ..Default::default()
         },
     });
@@ -110,32 +191,33 @@ fn setup_player(mut commands: Commands, model_assets: Res<ModelAssets>) {
     let mut body_entity = None;
     let mut leg_entities = Vec::new();
     cmd.with_children(|commands| {
-        body_entity = Some(
-            commands
-                .spawn_bundle((
-                    GlobalTransform::identity(),
-                    Transform::identity(),
-                    SpawnGltfNode(model_assets.player.clone(), "Body"),
-                    Animator::<Transform>::default(),
-                ))
-                .with_children(|commands| {
-                    for (node_name, leg_type) in
-                        [("RightLeg", PlayerLeg::Right), ("LeftLeg", PlayerLeg::Left)]
-                    {
-                        leg_entities.push({
-                            commands
-                                .spawn()
-                                .insert(Transform::identity())
-                                .insert(GlobalTransform::identity())
-                                .insert(SpawnGltfNode(model_assets.player.clone(), node_name))
-                                .insert(Animator::<Transform>::default())
-                                .insert(leg_type)
-                                .id()
-                        });
-                    }
-                })
-                .id(),
-        );
+        let mut body_cmd = commands.spawn_bundle((
+            GlobalTransform::identity(),
+            Transform::identity(),
+            SpawnGltfNode(model_assets.player.clone(), "Body"),
+            Animator::<Transform>::default(),
+        ));
+        if let Some(tint) = tint {
+            body_cmd.insert(PlayerPartTint(tint));
+        }
+        body_cmd.with_children(|commands| {
+            for (node_name, leg_type) in
+                [("RightLeg", PlayerLeg::Right), ("LeftLeg", PlayerLeg::Left)]
+            {
+                let mut leg_cmd = commands.spawn();
+                leg_cmd
+                    .insert(Transform::identity())
+                    .insert(GlobalTransform::identity())
+                    .insert(SpawnGltfNode(model_assets.player.clone(), node_name))
+                    .insert(Animator::<Transform>::default())
+                    .insert(leg_type);
+                if let Some(tint) = tint {
+                    leg_cmd.insert(PlayerPartTint(tint));
+                }
+                leg_entities.push(leg_cmd.id());
+            }
+        });
+        body_entity = Some(body_cmd.id());
     });
     cmd.insert(PlayerControl {
         max_speed: 20.0,
@@ -144,11 +226,13 @@ fn setup_player(mut commands: Commands, model_assets: Res<ModelAssets>) {
         jump_from_woodchip_power_coefficient: 200.0,
         jump_time_coefficient: 7.5,
         jump_potential: 0.0,
+        is_pushing_jump: false,
         last_stood_on: vector![0.0, 1.0],
         stood_on_potential: 0.0,
         stood_on_time_coefficient: 10.0,
         uphill_move_efficiency: 0.5,
         uphill_stop_efficiency: 1.0,
+        bump_power_coefficient: 300.0,
     });
     cmd.insert(PlayerStatusForAnimation {
         is_moving: false,
@@ -159,6 +243,10 @@ fn setup_player(mut commands: Commands, model_assets: Res<ModelAssets>) {
         leg_entities: leg_entities.try_into().unwrap(),
     });
     cmd.insert(IsPlayerAlive(true));
+    cmd.insert(index);
+    cmd.insert(controlled_by);
+    cmd.insert(PlayerInput::default());
+    cmd.insert(ScoreStatus::default());
     cmd.insert(DespawnWithLevel);
 }
 
@@ -169,53 +257,37 @@ enum PlayerLeg {
 }
 
 fn player_control(
-    time: Res<Time>,
-    input_views: Query<&InputView<InputBinding>>,
+    clock: Res<GameplayClock>,
     mut query: Query<(
         Entity,
+        &PlayerInput,
         &mut RigidBodyVelocityComponent,
         &RigidBodyMassPropsComponent,
         &IsPlayerAlive,
         &mut PlayerControl,
         &mut PlayerStatusForAnimation,
+        &mut ScoreStatus,
     )>,
     narrow_phase: Res<NarrowPhase>,
+    mut score_status: ResMut<ScoreStatus>,
+    mut jumped_writer: EventWriter<PlayerJumped>,
 ) {
-    let mut movement_value = 0.0;
-    let mut num_participating = 0;
-    let mut is_jumping = false;
-    for input_view in input_views.iter() {
-        for axis_value in input_view.axis(&InputBinding::MoveHorizontal) {
-            if !axis_value.1.released() {
-                num_participating += 1;
-                movement_value = axis_value.0
-            }
-        }
-        if matches!(
-            input_view.key(&InputBinding::Jump),
-            PressState::Pressed { .. }
-        ) {
-            is_jumping = true;
-        }
-    }
-    let movement_value = if 0 < num_participating {
-        movement_value / num_participating as f32
-    } else {
-        0.0
-    };
-    let target_speed = movement_value;
     for (
         player_entity,
+        player_input,
         mut velocity,
         mass_props,
         is_player_alive,
         mut player_control,
         mut player_status_for_animation,
+        mut player_score_status,
     ) in query.iter_mut()
     {
         if !is_player_alive.0 {
             continue;
         }
+        let is_jumping = player_input.jump;
+        let target_speed = player_input.move_horizontal;
         let standing_on = narrow_phase
             .contacts_with(player_entity.handle())
             .filter(|contact| contact.has_any_active_contact)
@@ -246,14 +318,23 @@ fn player_control(
             }
 
             player_control.stood_on_potential = (player_control.stood_on_potential
-                - time.delta().as_secs_f32() * player_control.stood_on_time_coefficient)
+                - clock.step.as_secs_f32() * player_control.stood_on_time_coefficient)
                 .max(0.0);
         }
+        let mut is_pushing_jump = false;
         if is_jumping {
             let to_deplete = player_control
                 .jump_potential
-                .min(time.delta().as_secs_f32() * player_control.jump_time_coefficient);
+                .min(clock.step.as_secs_f32() * player_control.jump_time_coefficient);
             if 0.0 < to_deplete {
+                is_pushing_jump = true;
+                if !player_control.is_pushing_jump {
+                    score_status.jumps += 1;
+                    player_score_status.jumps += 1;
+                    jumped_writer.send(PlayerJumped {
+                        player: player_entity,
+                    });
+                }
                 let before_depletion = player_control.jump_potential;
                 let after_depletion = before_depletion - to_deplete;
                 player_control.jump_potential = after_depletion;
@@ -269,6 +350,7 @@ fn player_control(
                 );
             }
         }
+        player_control.is_pushing_jump = is_pushing_jump;
 
         let mut up_now = vector![0.0, 1.0];
         up_now = (1.0 - player_control.stood_on_potential) * up_now
@@ -294,7 +376,7 @@ fn player_control(
             impulse.signum() * impulse.powi(4)
         };
         let mut impulse = movement_vector
-            * time.delta().as_secs_f32()
+            * clock.step.as_secs_f32()
             * player_control.impulse_coefficient
             * impulse;
         let uphill = impulse.normalize().dot(&vector![0.0, 1.0]);
@@ -401,46 +483,213 @@ fn player_animation(
 struct IsPlayerAlive(bool);
 
 fn kill_player(
-    mut commands: Commands,
-    mut reader: EventReader<IntersectionEvent>,
+    mut reader: EventReader<BodyIntersectionEvent>,
     mut players_query: Query<(
         &mut IsPlayerAlive,
         &mut RigidBodyDominanceComponent,
         &mut RigidBodyVelocityComponent,
+        &mut ScoreStatus,
     )>,
     chippers_query: Query<&Chipper>,
+    mut score_status: ResMut<ScoreStatus>,
+    mut killed_writer: EventWriter<PlayerKilled>,
 ) {
     for event in reader.iter() {
         if !event.intersecting {
             continue;
         }
-        let [player_entity, _chipper_entity] = some_or!(entities_ordered_by_type!(
-                [event.collider1.entity(), event.collider2.entity()],
+        let [player_entity, chipper_entity] = some_or!(entities_ordered_by_type!(
+                [event.body1, event.body2],
                 players_query,
                 chippers_query,
         ); continue);
-        let (mut is_player_alive, mut player_dominance, mut player_velocity) =
-            ok_or!(players_query.get_mut(player_entity); continue);
+        let (
+            mut is_player_alive,
+            mut player_dominance,
+            mut player_velocity,
+            mut player_score_status,
+        ) = ok_or!(players_query.get_mut(player_entity); continue);
         if !is_player_alive.0 {
             continue;
         }
         is_player_alive.0 = false;
+        player_score_status.cause_of_death = Some(CauseOfDeath::Chipped);
+        score_status.cause_of_death = Some(CauseOfDeath::Chipped);
         player_dominance.0 = RigidBodyDominance(127);
         player_velocity.0.linvel = vector![0.0, 5.0];
         player_velocity.0.angvel = 10.0;
-        commands
-            .entity(player_entity)
-            .insert(ParticleEffectType::Blood);
+        killed_writer.send(PlayerKilled {
+            player: player_entity,
+            chipper: chipper_entity,
+        });
+    }
+}
+
+fn bump_players(
+    game_mode: Res<GameMode>,
+    mut reader: EventReader<BodyContactEvent>,
+    mut players_query: Query<(
+        &PlayerControl,
+        &IsPlayerAlive,
+        &RigidBodyPositionComponent,
+        &mut RigidBodyVelocityComponent,
+        &RigidBodyMassPropsComponent,
+    )>,
+) {
+    if *game_mode != GameMode::Versus {
+        return;
+    }
+    for event in reader.iter() {
+        let (entity1, entity2) = if let BodyContactEvent::Started(entity1, entity2) = *event {
+            (entity1, entity2)
+        } else {
+            continue;
+        };
+        let (player_control1, is_player1_alive, position1, velocity1, _) =
+            ok_or!(players_query.get(entity1); continue);
+        let (player_control2, is_player2_alive, position2, velocity2, _) =
+            ok_or!(players_query.get(entity2); continue);
+        if !is_player1_alive.0 || !is_player2_alive.0 {
+            continue;
+        }
+        // The direction in which the first player pushes the second one
+        let direction =
+            (position2.position.translation.x - position1.position.translation.x).signum();
+        // Charging into the other player makes the bump stronger
+        let closing_speed = ((velocity1.linvel.x - velocity2.linvel.x) * direction).max(0.0);
+        let bump_impulses = [
+            (
+                entity1,
+                vector![-direction, 0.5]
+                    * player_control2.bump_power_coefficient
+                    * (1.0 + closing_speed / player_control2.max_speed),
+            ),
+            (
+                entity2,
+                vector![direction, 0.5]
+                    * player_control1.bump_power_coefficient
+                    * (1.0 + closing_speed / player_control1.max_speed),
+            ),
+        ];
+        for (player_entity, impulse) in bump_impulses {
+            let (_, _, _, mut velocity, mass_props) =
+                ok_or!(players_query.get_mut(player_entity); continue);
+            velocity.apply_impulse(mass_props, impulse);
+        }
+    }
+}
+
+fn update_survival_time(
+    clock: Res<GameplayClock>,
+    mut players_query: Query<(&IsPlayerAlive, &mut ScoreStatus)>,
+) {
+    for (is_player_alive, mut score_status) in players_query.iter_mut() {
+        if is_player_alive.0 {
+            score_status.time += clock.step;
+        }
+    }
+}
+
+fn track_touching_ground(
+    narrow_phase: Res<NarrowPhase>,
+    mut players_query: Query<(Entity, &mut ScoreStatus)>,
+    woodchips_query: Query<(), With<Woodchip>>,
+    collider_parents: Query<&ColliderParentComponent>,
+    mut score_status: ResMut<ScoreStatus>,
+) {
+    for (player_entity, mut player_score_status) in players_query.iter_mut() {
+        let player_handle = player_entity.handle();
+        let is_touching_ground = narrow_phase
+            .contacts_with(player_handle)
+            .filter(|contact| contact.has_any_active_contact)
+            .any(|contact| {
+                let other = if contact.collider1 == player_handle {
+                    contact.collider2
+                } else {
+                    contact.collider1
+                };
+                woodchips_query
+                    .get(collider_body_entity(other, &collider_parents))
+                    .is_err()
+            });
+        if is_touching_ground {
+            player_score_status.woodchips_without_touching_ground = 0;
+            score_status.woodchips_without_touching_ground = 0;
+        }
     }
 }
 
-fn game_over_when_player_falls_too_much(
-    players_query: Query<&RigidBodyPositionComponent, With<IsPlayerAlive>>,
+fn game_over_when_all_players_are_out(
+    game_mode: Res<GameMode>,
+    mut players_query: Query<(
+        &PlayerIndex,
+        &RigidBodyPositionComponent,
+        &mut IsPlayerAlive,
+        &mut ScoreStatus,
+    )>,
     mut state: ResMut<State<AppState>>,
+    mut versus_winner: ResMut<VersusWinner>,
+    mut score_status: ResMut<ScoreStatus>,
 ) {
-    for player_position in players_query.iter() {
+    let mut num_players = 0;
+    let mut num_players_in = 0;
+    let mut players_alive = Vec::new();
+    for (player_index, player_position, mut is_player_alive, mut player_score_status) in
+        players_query.iter_mut()
+    {
+        num_players += 1;
         if player_position.position.translation.y < -4.0 {
-            state.set(AppState::Menu(MenuState::GameOver)).unwrap();
+            // Players who fell without getting chipped are out as well
+            if is_player_alive.0 {
+                is_player_alive.0 = false;
+                player_score_status.cause_of_death = Some(CauseOfDeath::Fell);
+                score_status.cause_of_death = Some(CauseOfDeath::Fell);
+            }
+        } else {
+            num_players_in += 1;
+            if is_player_alive.0 {
+                players_alive.push(*player_index);
+            }
+        }
+    }
+    let is_round_over = match *game_mode {
+        GameMode::Versus if 1 < num_players => num_players_in <= 1,
+        // A lone versus player plays until they are out - others may still join
+        GameMode::SinglePlayer | GameMode::CoOp | GameMode::Versus => {
+            0 < num_players && num_players_in == 0
+        }
+    };
+    if is_round_over {
+        if *game_mode == GameMode::Versus {
+            versus_winner.0 = players_alive.first().copied();
+        }
+        // The gameplay stage may run more than once per frame - so the game over may already be
+        // queued.
+        state
+            .overwrite_set(AppState::Menu(MenuState::GameOver))
+            .unwrap();
+    }
+}
+
+fn tint_player_parts(
+    mut event_reader: EventReader<GltfNodeAddedEvent>,
+    parts_query: Query<(&PlayerPartTint, &Children)>,
+    mut materials_query: Query<&mut Handle<StandardMaterial>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for GltfNodeAddedEvent(entity) in event_reader.iter() {
+        let (PlayerPartTint(tint), children) = ok_or!(parts_query.get(*entity); continue);
+        for child in children.iter() {
+            let mut material_handle = ok_or!(materials_query.get_mut(*child); continue);
+            let mut material = some_or!(materials.get(&*material_handle); continue).clone();
+            let base_color = material.base_color;
+            material.base_color = Color::rgba(
+                base_color.r() * tint.r(),
+                base_color.g() * tint.g(),
+                base_color.b() * tint.b(),
+                base_color.a(),
+            );
+            *material_handle = materials.add(material);
         }
     }
 }
@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+use bevy_asset_loader::{AssetCollection, AssetCollectionApp};
+
+use crate::game_systems::gameplay_system_set;
+use crate::global_types::{
+    GameplayStage, GameplaySystemLabel, ScoreFormula, ScoreStatus, TrunkChipped, WoodchipCleared,
+};
+use crate::loading::{RonAsset, RonAssetLoader};
+
+pub struct ScoringPlugin;
+
+impl Plugin for ScoringPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<ScoreFormula>();
+        app.init_asset_loader::<RonAssetLoader<ScoreFormula>>();
+        app.init_collection::<ScoreFormulaAssets>();
+        app.add_system(apply_loaded_score_formula);
+        app.add_system_set_to_stage(GameplayStage, {
+            gameplay_system_set()
+                .with_system(
+                    count_chipped_logs
+                        .label(GameplaySystemLabel::UpdateScore)
+                        .after(GameplaySystemLabel::SendGameplayEvents),
+                )
+                .with_system(
+                    count_cleared_woodchips
+                        .label(GameplaySystemLabel::UpdateScore)
+                        .after(GameplaySystemLabel::SendGameplayEvents),
+                )
+        });
+    }
+}
+
+#[derive(AssetCollection)]
+struct ScoreFormulaAssets {
+    #[asset(path = "game.score.ron")]
+    score_formula: Handle<ScoreFormula>,
+}
+
+impl RonAsset for ScoreFormula {
+    const EXTENSIONS: &'static [&'static str] = &["score.ron"];
+}
+
+/// Also picks up changes when the file is hot-reloaded.
+fn apply_loaded_score_formula(
+    mut reader: EventReader<AssetEvent<ScoreFormula>>,
+    score_formula_assets: Res<ScoreFormulaAssets>,
+    score_formulas: Res<Assets<ScoreFormula>>,
+    mut score_formula: ResMut<ScoreFormula>,
+) {
+    for event in reader.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+            if *handle == score_formula_assets.score_formula {
+                if let Some(loaded) = score_formulas.get(handle) {
+                    *score_formula = loaded.clone();
+                }
+            }
+        }
+    }
+}
+
+fn count_chipped_logs(
+    mut reader: EventReader<TrunkChipped>,
+    mut score_status: ResMut<ScoreStatus>,
+) {
+    for _ in reader.iter() {
+        score_status.logs_chipped += 1;
+    }
+}
+
+fn count_cleared_woodchips(
+    mut reader: EventReader<WoodchipCleared>,
+    score_formula: Res<ScoreFormula>,
+    mut score_status: ResMut<ScoreStatus>,
+    mut players_query: Query<&mut ScoreStatus>,
+) {
+    for event in reader.iter() {
+        let now = score_status.time;
+        score_status.clear_woodchip(&score_formula, now);
+        if let Ok(mut player_score_status) = players_query.get_mut(event.player) {
+            player_score_status.clear_woodchip(&score_formula, now);
+        }
+    }
+}
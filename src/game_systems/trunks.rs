@@ -2,11 +2,18 @@ use std::time::Duration;
 
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
+use rand::Rng;
 
+use crate::game_systems::gameplay_system_set;
 use crate::global_types::{
-    AppState, Chipper, DespawnWithLevel, ScoreStatus, SpawnsWoodchips, Trunk,
+    Chipper, DespawnWithLevel, Difficulty, GameRng, GameplayStage, GameplaySystemLabel,
+    InterpolatedPosition, PlayerControl, SpawnsWoodchips, Trunk, TrunkChipped, TrunkEnteredChipper,
+    TrunkLost,
 };
-use crate::gltf_spawner::{SpawnCollider, SpawnGltfNode};
+use crate::gltf_spawner::{
+    BodyIntersectionEvent, ColliderProperties, SpawnCollider, SpawnGltfNode,
+};
+use crate::level::{CurrentLevel, Level};
 use crate::loading::ModelAssets;
 use crate::utils::{entities_ordered_by_type, ok_or, some_or};
 
@@ -14,12 +21,14 @@ pub struct TrunksPlugin;
 
 impl Plugin for TrunksPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set({
-            SystemSet::on_update(AppState::Game)
-                .with_system(spawn_trunk)
-                .with_system(handle_trunk_hitting_chipper)
+        app.add_system_set_to_stage(GameplayStage, {
+            gameplay_system_set()
+                .with_system(spawn_trunk.after(GameplaySystemLabel::UpdateDifficulty))
+                .with_system(
+                    handle_trunk_hitting_chipper.label(GameplaySystemLabel::SendGameplayEvents),
+                )
                 .with_system(chippers_resist_trunk)
-                .with_system(handle_lost_trunks)
+                .with_system(handle_lost_trunks.label(GameplaySystemLabel::SendGameplayEvents))
         });
     }
 }
@@ -28,54 +37,69 @@ fn spawn_trunk(
     mut commands: Commands,
     model_assets: Res<ModelAssets>,
     current_logs: Query<&Trunk>,
+    mut rng: ResMut<GameRng>,
+    players_query: Query<(), With<PlayerControl>>,
+    levels: Res<Assets<Level>>,
+    current_level: Res<CurrentLevel>,
+    difficulty: Res<Difficulty>,
 ) {
+    if players_query.iter().next().is_none() {
+        // Wait for the co-op players to join
+        return;
+    }
+    let level = some_or!(levels.get(&current_level.0); return);
     let mut num_trunks = 0;
+    let mut num_trunks_in_flight = 0;
     for trunk in current_logs.iter() {
         if matches!(trunk, Trunk::Free) {
-            return;
+            num_trunks_in_flight += 1;
         }
         num_trunks += 1;
     }
-    if 2 <= num_trunks {
+    if level.max_trunks + difficulty.extra_trunks <= num_trunks
+        || level.max_trunks_in_flight + difficulty.extra_trunks_in_flight <= num_trunks_in_flight
+    {
         return;
     }
+    // Only roll for the launcher when there is a choice, so that single-launcher levels keep the
+    // same trunk sequence for the same seed.
+    let launcher = if level.trunk_launchers.len() == 1 {
+        &level.trunk_launchers[0]
+    } else {
+        &level.trunk_launchers[rng.gen_range(0..level.trunk_launchers.len())]
+    };
     let mut cmd = commands.spawn();
     cmd.insert_bundle(RigidBodyBundle {
         body_type: RigidBodyType::Dynamic.into(),
-        mass_properties: RigidBodyMassProps {
-            local_mprops: MassProperties {
-                local_com: point![0.0, 0.0],
-                inv_mass: 1.0 / 3000.0,
-                inv_principal_inertia_sqrt: 1.0 / 300.0,
-            },
-            ..Default::default()
-        }
-        .into(),
-        position: point![10.0, 5.0].into(),
+        position: point![launcher.position.0, launcher.position.1].into(),
         velocity: RigidBodyVelocity {
             linvel: {
-                let x_velovity = -15.0 + 10.0 * rand::random::<f32>();
-                let y_velovity = 2.0 + 4.0 * rand::random::<f32>();
+                let x_velovity = launcher
+                    .velocity_x
+                    .widened(difficulty.launch_spread_factor)
+                    .sample(&mut **rng);
+                let y_velovity = launcher
+                    .velocity_y
+                    .widened(difficulty.launch_spread_factor)
+                    .sample(&mut **rng);
                 vector![x_velovity, y_velovity]
             },
-            angvel: 4.0 * (rand::random::<f32>() - 0.5),
+            angvel: launcher.angular_velocity.sample(&mut **rng),
         }
         .into(),
         ..Default::default()
     });
-    cmd.insert(RigidBodyPositionSync::Discrete);
+    cmd.insert(InterpolatedPosition::default());
     cmd.insert(SpawnCollider {
         gltf: model_assets.trunk.clone(),
         node_name: "Collider",
-        collider_type: ColliderType::Solid,
-        material: ColliderMaterial {
-            // friction: 2.0,
-            // restitution: todo!(),
-            // friction_combine_rule: todo!(),
-            // restitution_combine_rule: todo!(),
+        properties: Default::default(),
+        defaults: ColliderProperties {
+            // Over the 1x10 cuboid, a mass of about 3000 - as heavy as the trunks were before their
+            // mass came from the collider. The rotational inertia comes from the cuboid too.
+            density: Some(300.0),
             ..Default::default()
         },
-        flags: Default::default(),
     });
     cmd.insert(Transform::from_xyz(0.0, 2.0, 0.0));
     cmd.insert(GlobalTransform::identity());
@@ -85,15 +109,16 @@ fn spawn_trunk(
 }
 
 fn handle_trunk_hitting_chipper(
-    mut reader: EventReader<IntersectionEvent>,
+    mut reader: EventReader<BodyIntersectionEvent>,
     mut trunks_query: Query<(&mut Trunk, &mut RigidBodyTypeComponent)>,
     chippers_query: Query<&Chipper>,
     mut commands: Commands,
-    mut score_status: ResMut<ScoreStatus>,
+    mut entered_writer: EventWriter<TrunkEnteredChipper>,
+    mut chipped_writer: EventWriter<TrunkChipped>,
 ) {
     for event in reader.iter() {
         let [trunk_entity, chipper_entity] = some_or!(entities_ordered_by_type!(
-                [event.collider1.entity(), event.collider2.entity()],
+                [event.body1, event.body2],
                 trunks_query,
                 chippers_query,
         ); continue);
@@ -112,12 +137,18 @@ fn handle_trunk_hitting_chipper(
                     trunk_chippers.insert(chipper_entity);
                 }
             }
+            entered_writer.send(TrunkEnteredChipper {
+                trunk: trunk_entity,
+                chipper: chipper_entity,
+            });
         } else if let Trunk::InChipper(trunk_chippers) = &mut *trunk {
             if trunk_chippers.contains(&chipper_entity) {
                 trunk_chippers.remove(&chipper_entity);
                 if trunk_chippers.is_empty() {
-                    score_status.logs_chipped += 1;
                     commands.entity(trunk_entity).despawn_recursive();
+                    chipped_writer.send(TrunkChipped {
+                        trunk: trunk_entity,
+                    });
                 }
             }
         }
@@ -127,10 +158,14 @@ fn handle_trunk_hitting_chipper(
 fn handle_lost_trunks(
     mut commands: Commands,
     trunks: Query<(Entity, &Trunk, &RigidBodyPositionComponent)>,
+    mut lost_writer: EventWriter<TrunkLost>,
 ) {
     for (trunk_entity, trunk, trunk_position) in trunks.iter() {
         if matches!(trunk, Trunk::Free) && trunk_position.position.translation.y < -8.0 {
             commands.entity(trunk_entity).despawn_recursive();
+            lost_writer.send(TrunkLost {
+                trunk: trunk_entity,
+            });
         }
     }
 }
@@ -2,12 +2,17 @@ use std::time::Duration;
 
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
-use bevy_tweening::{Animator, AnimatorState};
+use rand::Rng;
 
+use crate::game_systems::gameplay_system_set;
 use crate::global_types::{
-    AppState, Chipper, DespawnWithLevel, PlayerControl, ScoreStatus, SpawnsWoodchips, Woodchip,
+    Chipper, ChipperJammed, ChipperUnjammed, DespawnWithLevel, Difficulty, GameRng, GameplayClock,
+    GameplayStage, GameplaySystemLabel, InterpolatedPosition, PlayerControl, SpawnsWoodchips,
+    Woodchip, WoodchipCleared,
+};
+use crate::gltf_spawner::{
+    BodyContactEvent, BodyIntersectionEvent, ColliderProperties, SpawnCollider, SpawnGltfNode,
 };
-use crate::gltf_spawner::{SpawnCollider, SpawnGltfNode};
 use crate::loading::ModelAssets;
 use crate::utils::{entities_ordered_by_type, ok_or, some_or};
 
@@ -15,23 +20,29 @@ pub struct WoodshipsPlugin;
 
 impl Plugin for WoodshipsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set({
-            SystemSet::on_update(AppState::Game)
-                .with_system(spawn_woodchips)
-                .with_system(handle_chip_hitting_chipper)
-                .with_system(handle_player_jump_from_chipper)
+        app.add_system_set_to_stage(GameplayStage, {
+            gameplay_system_set()
+                .with_system(spawn_woodchips.after(GameplaySystemLabel::UpdateDifficulty))
+                .with_system(
+                    handle_chip_hitting_chipper.label(GameplaySystemLabel::SendGameplayEvents),
+                )
+                .with_system(
+                    handle_player_jump_from_chipper.label(GameplaySystemLabel::SendGameplayEvents),
+                )
         });
     }
 }
 
 fn spawn_woodchips(
     mut commands: Commands,
-    time: Res<Time>,
+    clock: Res<GameplayClock>,
     model_assets: Res<ModelAssets>,
     mut spawners_query: Query<(&RigidBodyPositionComponent, &mut SpawnsWoodchips)>,
+    mut rng: ResMut<GameRng>,
+    difficulty: Res<Difficulty>,
 ) {
     for (spawner_position, mut spawner) in spawners_query.iter_mut() {
-        if spawner.0.tick(time.delta()).just_finished() {
+        if spawner.0.tick(clock.step).just_finished() {
             if !spawner.0.duration().is_zero() {
                 let spawn_from_position = {
                     let pos1 = spawner_position.0.position * point![-1.0, 0.0];
@@ -51,15 +62,6 @@ fn spawn_woodchips(
                 let mut cmd = commands.spawn();
                 cmd.insert_bundle(RigidBodyBundle {
                     body_type: RigidBodyType::Dynamic.into(),
-                    mass_properties: RigidBodyMassProps {
-                        local_mprops: MassProperties {
-                            local_com: point![0.0, 0.0],
-                            inv_mass: 1.0 / 30.0,
-                            inv_principal_inertia_sqrt: 1.0 / 3.0,
-                        },
-                        ..Default::default()
-                    }
-                    .into(),
                     position: Isometry {
                         translation: spawn_from_position.into(),
                         rotation: spawner_position.0.position.rotation,
@@ -67,28 +69,26 @@ fn spawn_woodchips(
                     .into(),
                     velocity: RigidBodyVelocity {
                         linvel: {
-                            let x_velovity = 3.0 + 2.0 * rand::random::<f32>();
-                            let y_velovity = 5.0 + 7.0 * rand::random::<f32>();
+                            let x_velovity = 3.0 + 2.0 * rng.gen::<f32>();
+                            let y_velovity = 5.0 + 7.0 * rng.gen::<f32>();
                             vector![x_velovity * spawn_direction.x, y_velovity]
                         },
-                        angvel: 10.0 * (rand::random::<f32>() - 0.5),
+                        angvel: 10.0 * (rng.gen::<f32>() - 0.5),
                     }
                     .into(),
                     ..Default::default()
                 });
-                cmd.insert(RigidBodyPositionSync::Discrete);
+                cmd.insert(InterpolatedPosition::default());
                 cmd.insert(SpawnCollider {
                     gltf: model_assets.woodchip.clone(),
                     node_name: "Collider",
-                    collider_type: ColliderType::Solid,
-                    material: ColliderMaterial {
-                        // friction: 2.0,
-                        // restitution: todo!(),
-                        // friction_combine_rule: todo!(),
-                        // restitution_combine_rule: todo!(),
+                    properties: Default::default(),
+                    defaults: ColliderProperties {
+                        // Over the hull's area of about 0.2, a mass of about 30 - as heavy as the
+                        // woodchips were before their mass came from the collider
+                        density: Some(150.0),
                         ..Default::default()
                     },
-                    flags: Default::default(),
                 });
                 cmd.insert(Transform::from_xyz(0.0, 0.0, 0.0));
                 cmd.insert(GlobalTransform::identity());
@@ -96,7 +96,7 @@ fn spawn_woodchips(
                 cmd.insert(Woodchip::Free);
                 cmd.insert(DespawnWithLevel);
             }
-            let next_chip_in = 1.0 + 5.0 * rand::random::<f32>();
+            let next_chip_in = (1.0 + 5.0 * rng.gen::<f32>()) * difficulty.woodchip_interval_factor;
             spawner
                 .0
                 .set_duration(Duration::from_secs_f32(next_chip_in));
@@ -106,25 +106,24 @@ fn spawn_woodchips(
 }
 
 fn handle_chip_hitting_chipper(
-    mut reader: EventReader<IntersectionEvent>,
+    mut reader: EventReader<BodyIntersectionEvent>,
     mut woodchips_query: Query<(
         &RigidBodyPositionComponent,
         &mut RigidBodyTypeComponent,
         &mut Woodchip,
     )>,
-    mut chippers_query: Query<(&mut Chipper, &Children)>,
-    mut saws_query: Query<&mut Animator<Transform>>,
+    mut chippers_query: Query<&mut Chipper>,
     mut commands: Commands,
+    mut jammed_writer: EventWriter<ChipperJammed>,
 ) {
     for event in reader.iter() {
         let [woodchip_entity, chipper_entity] = some_or!(entities_ordered_by_type!(
-                [event.collider1.entity(), event.collider2.entity()],
+                [event.body1, event.body2],
                 woodchips_query,
                 chippers_query,
         ); continue);
         if event.intersecting {
-            let (mut chipper, chipper_children) =
-                ok_or!(chippers_query.get_mut(chipper_entity); continue);
+            let mut chipper = ok_or!(chippers_query.get_mut(chipper_entity); continue);
             if !matches!(*chipper, Chipper::Free) {
                 continue;
             }
@@ -139,36 +138,33 @@ fn handle_chip_hitting_chipper(
                 *woodchip = Woodchip::StuckInChipper(chipper_entity);
                 woodchip_rigid_body_type.0 = RigidBodyType::Static;
                 *chipper = Chipper::Jammed;
-                for saw_entity in chipper_children.iter() {
-                    if let Ok(mut saw_animator) = saws_query.get_mut(*saw_entity) {
-                        saw_animator.state = AnimatorState::Paused;
-                    }
-                }
+                jammed_writer.send(ChipperJammed {
+                    chipper: chipper_entity,
+                    woodchip: woodchip_entity,
+                });
             }
         }
     }
 }
 
 fn handle_player_jump_from_chipper(
-    mut reader: EventReader<ContactEvent>,
+    mut reader: EventReader<BodyContactEvent>,
     woodchips_query: Query<&Woodchip>,
     mut player_query: Query<(
         &mut PlayerControl,
         &mut RigidBodyVelocityComponent,
         &RigidBodyMassPropsComponent,
     )>,
-    mut chippers_query: Query<(&mut Chipper, &Children)>,
-    mut saws_query: Query<&mut Animator<Transform>>,
+    mut chippers_query: Query<&mut Chipper>,
     mut commands: Commands,
-    mut score_status: ResMut<ScoreStatus>,
+    mut unjammed_writer: EventWriter<ChipperUnjammed>,
+    mut cleared_writer: EventWriter<WoodchipCleared>,
 ) {
     for event in reader.iter() {
-        if let ContactEvent::Started(handle1, handle2) = event {
-            if let Some([woodchip_entity, player_entity]) = entities_ordered_by_type!(
-                [handle1.entity(), handle2.entity()],
-                woodchips_query,
-                player_query,
-            ) {
+        if let BodyContactEvent::Started(entity1, entity2) = *event {
+            if let Some([woodchip_entity, player_entity]) =
+                entities_ordered_by_type!([entity1, entity2], woodchips_query, player_query,)
+            {
                 let chipper_to_unjam = if let Ok(Woodchip::StuckInChipper(chipper)) =
                     woodchips_query.get(woodchip_entity)
                 {
@@ -189,17 +185,20 @@ fn handle_player_jump_from_chipper(
                     );
                 }
 
-                let (mut chipper, chipper_children) =
-                    ok_or!(chippers_query.get_mut(*chipper_to_unjam); continue);
+                let chipper_to_unjam = *chipper_to_unjam;
+                let mut chipper = ok_or!(chippers_query.get_mut(chipper_to_unjam); continue);
                 *chipper = Chipper::Free;
-                for saw_entity in chipper_children.iter() {
-                    if let Ok(mut saw_animator) = saws_query.get_mut(*saw_entity) {
-                        saw_animator.state = AnimatorState::Playing;
-                    }
-                }
+                unjammed_writer.send(ChipperUnjammed {
+                    chipper: chipper_to_unjam,
+                    player: player_entity,
+                });
 
-                score_status.woodchips_cleared += 1;
                 commands.entity(woodchip_entity).despawn_recursive();
+                cleared_writer.send(WoodchipCleared {
+                    woodchip: woodchip_entity,
+                    chipper: chipper_to_unjam,
+                    player: player_entity,
+                });
             }
         }
     }
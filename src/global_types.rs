@@ -1,10 +1,15 @@
 use std::time::Duration;
 
 use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
 use bevy::utils::HashSet;
 use bevy_rapier2d::na::Vector2;
+use bevy_rapier2d::prelude::{Isometry, Real};
 use ezinput::prelude::BindingTypeView;
 use ezinput_macros::BindingTypeView;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Hash, Debug, PartialEq, Eq)]
 pub enum AppState {
@@ -17,13 +22,27 @@ pub enum AppState {
 #[derive(Clone, Hash, Debug, PartialEq, Eq)]
 pub enum MenuState {
     Main,
+    LevelSelect,
     Pause,
     GameOver,
+    LevelComplete,
+    HighScores,
+    Settings,
+    Controls,
 }
 
 #[derive(Component)]
 pub struct DespawnWithLevel;
 
+/// Renders the rigid body between its positions of the last two gameplay steps, so that it moves
+/// smoothly when the frame rate does not match the gameplay step rate.
+#[derive(Component, Default)]
+pub struct InterpolatedPosition {
+    /// The position before the last gameplay step. `None` when the body should be rendered where
+    /// it is.
+    pub previous: Option<Isometry<Real>>,
+}
+
 #[derive(BindingTypeView, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum InputBinding {
     MoveHorizontal,
@@ -31,6 +50,180 @@ pub enum InputBinding {
     Pause,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    SinglePlayer,
+    /// Every input device controls its own player. Players join by pressing Jump.
+    CoOp,
+    /// Like [`GameMode::CoOp`], but players can bump each other and the round ends when only one
+    /// player remains.
+    Versus,
+}
+
+impl GameMode {
+    pub fn players_join_by_jumping(&self) -> bool {
+        match self {
+            Self::SinglePlayer => false,
+            Self::CoOp | Self::Versus => true,
+        }
+    }
+}
+
+impl Default for GameMode {
+    fn default() -> Self {
+        Self::SinglePlayer
+    }
+}
+
+/// Where the players' input comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputSource {
+    Live,
+    Replay,
+}
+
+impl Default for InputSource {
+    fn default() -> Self {
+        Self::Live
+    }
+}
+
+/// The input that drives a player this frame - either read from the input devices or played back
+/// from a replay.
+#[derive(Component, Default, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInput {
+    pub move_horizontal: f32,
+    pub jump: bool,
+    /// Pause was pressed this frame.
+    #[serde(default)]
+    pub pause: bool,
+}
+
+/// Set by the on-screen touch controls. Counts as one more input device for the players controlled
+/// by [`PlayerControlledBy::AnyInput`].
+#[derive(Default, PartialEq)]
+pub struct TouchControlsInput {
+    pub move_horizontal: f32,
+    pub jump: bool,
+}
+
+/// Paces the fixed-step [`GameplayStage`], which runs all the gameplay systems.
+pub struct GameplayClock {
+    pub step: Duration,
+    /// Run one step per frame, no matter how long the frame took. For replay playback, which does
+    /// not need to run in real time.
+    pub one_step_per_frame: bool,
+    /// When the frames are too long to catch up with within this many steps, the game slows down
+    /// instead - running more steps would only make the frames even longer.
+    pub max_steps_per_frame: u32,
+    accumulated: Duration,
+    steps_this_frame: u32,
+    steps_taken: u64,
+}
+
+impl GameplayClock {
+    pub fn from_rate(steps_per_second: f64) -> Self {
+        Self {
+            step: Duration::from_secs_f64(1.0 / steps_per_second),
+            one_step_per_frame: false,
+            max_steps_per_frame: 5,
+            accumulated: Duration::ZERO,
+            steps_this_frame: 0,
+            steps_taken: 0,
+        }
+    }
+
+    /// To be called once per frame, with the time passed since the last frame.
+    pub(crate) fn start_frame(&mut self, delta: Duration) {
+        self.steps_this_frame = 0;
+        if self.one_step_per_frame {
+            self.accumulated = self.step;
+        } else {
+            self.accumulated += delta;
+        }
+    }
+
+    /// Returns whether or not another gameplay step is due this frame, and counts it if it is.
+    pub(crate) fn take_step(&mut self) -> bool {
+        if self.accumulated < self.step {
+            return false;
+        }
+        if self.max_steps_per_frame <= self.steps_this_frame {
+            // Drop the whole steps that were not caught up with, or the next frames would be
+            // too long as well. The rest is kept for the interpolation.
+            self.accumulated =
+                Duration::from_nanos((self.accumulated.as_nanos() % self.step.as_nanos()) as u64);
+            return false;
+        }
+        self.accumulated -= self.step;
+        self.steps_this_frame += 1;
+        self.steps_taken += 1;
+        true
+    }
+
+    /// How many gameplay steps were taken since the game started. The clock keeps stepping while
+    /// the game is paused - only the gameplay systems stop.
+    pub fn steps_taken(&self) -> u64 {
+        self.steps_taken
+    }
+
+    /// How far the frame is into the next gameplay step, from 0.0 to 1.0 - for rendering between
+    /// the last two steps.
+    pub fn step_fraction(&self) -> f32 {
+        if self.one_step_per_frame {
+            // Each frame shows exactly the step it ran
+            1.0
+        } else {
+            (self.accumulated.as_secs_f64() / self.step.as_secs_f64()).min(1.0) as f32
+        }
+    }
+}
+
+impl Default for GameplayClock {
+    fn default() -> Self {
+        Self::from_rate(60.0)
+    }
+}
+
+#[derive(StageLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct GameplayStage;
+
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub enum GameplaySystemLabel {
+    JoinPlayers,
+    OverrideInput,
+    UpdateDifficulty,
+    /// The physics step, which runs at the start of every gameplay step. All the other gameplay
+    /// systems run after it.
+    StepPhysics,
+    /// Systems that send the gameplay events. Gameplay systems that react to these events should
+    /// run after them, so that the reaction happens in the same step.
+    SendGameplayEvents,
+    /// Systems that count the gameplay events into the [`ScoreStatus`]. Systems that reset parts
+    /// of it run before them, so that whatever reads the score after them sees every count before
+    /// it is reset.
+    UpdateScore,
+}
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerControlledBy {
+    /// Every input device.
+    AnyInput,
+    /// The entity that holds the `InputView<InputBinding>`.
+    InputView(Entity),
+    Replay,
+}
+
+/// The order in which the player joined the level.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PlayerIndex(pub usize);
+
+pub struct JoinPlayerEvent(pub PlayerControlledBy);
+
+/// Who won the last versus round. `None` when the last players went out at the same time.
+#[derive(Default)]
+pub struct VersusWinner(pub Option<PlayerIndex>);
+
 #[derive(Component)]
 pub struct PlayerControl {
     pub max_speed: f32,
@@ -39,11 +232,14 @@ pub struct PlayerControl {
     pub jump_from_woodchip_power_coefficient: f32,
     pub jump_time_coefficient: f32,
     pub jump_potential: f32,
+    /// Whether the jump potential was being turned into upward impulse in the last step.
+    pub is_pushing_jump: bool,
     pub last_stood_on: Vector2<f32>,
     pub stood_on_potential: f32,
     pub stood_on_time_coefficient: f32,
     pub uphill_move_efficiency: f32,
     pub uphill_stop_efficiency: f32,
+    pub bump_power_coefficient: f32,
 }
 
 #[derive(Component)]
@@ -52,6 +248,18 @@ pub enum Chipper {
     Jammed,
 }
 
+/// What the chipper looks like - updated from its [`Chipper`] state and the trunks in it.
+#[derive(Component, PartialEq)]
+pub enum ChipperEffect {
+    NoEffect,
+    ChippingWood,
+    Smoking,
+}
+
+/// How long the chipper has been jammed. Zero while it is free.
+#[derive(Component, Default)]
+pub struct JamDuration(pub Duration);
+
 #[derive(Component)]
 pub enum Trunk {
     Free,
@@ -67,6 +275,52 @@ pub enum Woodchip {
     StuckInChipper(Entity),
 }
 
+/// A trunk started touching a chipper - either its first chipper or an additional one.
+pub struct TrunkEnteredChipper {
+    pub trunk: Entity,
+    pub chipper: Entity,
+}
+
+/// A trunk left the last chipper it was in, and was despawned.
+pub struct TrunkChipped {
+    pub trunk: Entity,
+}
+
+/// A trunk fell off the arena without being chipped, and was despawned.
+pub struct TrunkLost {
+    pub trunk: Entity,
+}
+
+/// A wood chip got stuck in a chipper.
+pub struct ChipperJammed {
+    pub chipper: Entity,
+    pub woodchip: Entity,
+}
+
+/// A player jumped off the wood chip that jammed the chipper.
+pub struct ChipperUnjammed {
+    pub chipper: Entity,
+    pub player: Entity,
+}
+
+/// Sent together with [`ChipperUnjammed`], after the wood chip was despawned.
+pub struct WoodchipCleared {
+    pub woodchip: Entity,
+    pub chipper: Entity,
+    pub player: Entity,
+}
+
+/// A player started a jump - either from the ground or from a wood chip.
+pub struct PlayerJumped {
+    pub player: Entity,
+}
+
+/// A player fell into a chipper.
+pub struct PlayerKilled {
+    pub player: Entity,
+    pub chipper: Entity,
+}
+
 #[derive(Component, Copy, Clone, PartialEq, Debug)]
 pub enum ParticleEffectType {
     ChippingWood,
@@ -74,18 +328,191 @@ pub enum ParticleEffectType {
     Blood,
 }
 
-#[derive(Default)]
+/// Used both as a resource for the whole team and as a component on each player.
+#[derive(Component, Default)]
 pub struct ScoreStatus {
     pub time: Duration,
     pub logs_chipped: u32,
     pub woodchips_cleared: u32,
+    /// The points from clearing wood chips, with the combo multiplier already applied.
+    pub woodchip_points: u64,
+    /// How many wood chips were cleared in quick succession.
+    pub combo: u32,
+    pub last_woodchip_cleared_at: Option<Duration>,
+    /// Wood chips cleared since the player last stood on anything that is not a wood chip.
+    pub woodchips_without_touching_ground: u32,
+    /// The longest time any chipper stayed jammed.
+    pub longest_jam: Duration,
+    pub jumps: u32,
+    /// For the whole team - how the last player went out.
+    pub cause_of_death: Option<CauseOfDeath>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CauseOfDeath {
+    Chipped,
+    Fell,
+}
+
+impl CauseOfDeath {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            CauseOfDeath::Chipped => "Chipped",
+            CauseOfDeath::Fell => "Fell",
+        }
+    }
 }
 
 impl ScoreStatus {
     pub fn format_time(&self) -> String {
-        let time_in_seconds = self.time.as_secs_f32();
-        let only_minutes = time_in_seconds as u32 / 60;
-        let only_seconds = time_in_seconds % 60.0;
-        format!("{:02}:{:04.1}", only_minutes, only_seconds)
+        format_time(self.time)
+    }
+
+    pub fn score(&self, formula: &ScoreFormula) -> u64 {
+        (self.time.as_secs_f32() * formula.points_per_second) as u64
+            + self.logs_chipped as u64 * formula.points_per_log
+            + self.woodchip_points
+    }
+
+    pub fn combo_multiplier(&self, formula: &ScoreFormula) -> f32 {
+        (1.0 + self.combo as f32 * formula.combo_step).min(formula.max_combo_multiplier)
+    }
+
+    /// `now` is the time of the run - the `time` of the per-player statuses does not advance.
+    pub fn clear_woodchip(&mut self, formula: &ScoreFormula, now: Duration) {
+        match self.last_woodchip_cleared_at {
+            Some(last_woodchip_cleared_at)
+                if now <= last_woodchip_cleared_at + formula.combo_window =>
+            {
+                self.combo += 1;
+            }
+            _ => {
+                self.combo = 0;
+            }
+        }
+        self.last_woodchip_cleared_at = Some(now);
+        self.woodchips_cleared += 1;
+        self.woodchips_without_touching_ground += 1;
+        self.woodchip_points +=
+            (formula.points_per_woodchip as f32 * self.combo_multiplier(formula)) as u64;
+    }
+}
+
+/// How [`ScoreStatus`] is turned into a single number. Loaded from a `.score.ron` file - the
+/// default is only used until it gets loaded.
+#[derive(TypeUuid, Deserialize, Debug, Clone)]
+#[uuid = "3c0f5e8a-6b2d-4a71-9d3e-7f1a2c4b8e60"]
+pub struct ScoreFormula {
+    pub points_per_second: f32,
+    pub points_per_log: u64,
+    /// Multiplied by the combo multiplier.
+    pub points_per_woodchip: u64,
+    /// Clearing a wood chip within this time from the previous one grows the combo. Clearing it
+    /// later starts a new combo. In seconds.
+    #[serde(deserialize_with = "deserialize_seconds")]
+    pub combo_window: Duration,
+    /// Added to the combo multiplier for each wood chip in the combo.
+    pub combo_step: f32,
+    pub max_combo_multiplier: f32,
+    /// The combo is lost when any chipper stays jammed for this long. In seconds.
+    #[serde(deserialize_with = "deserialize_seconds")]
+    pub combo_jam_limit: Duration,
+}
+
+fn deserialize_seconds<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    let seconds = f32::deserialize(deserializer)?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(serde::de::Error::custom(format!(
+            "invalid number of seconds {}",
+            seconds
+        )));
+    }
+    Ok(Duration::from_secs_f32(seconds))
+}
+
+impl Default for ScoreFormula {
+    fn default() -> Self {
+        Self {
+            points_per_second: 10.0,
+            points_per_log: 100,
+            points_per_woodchip: 50,
+            combo_window: Duration::from_secs(3),
+            combo_step: 0.5,
+            max_combo_multiplier: 5.0,
+            combo_jam_limit: Duration::from_secs(5),
+        }
+    }
+}
+
+pub fn format_time(time: Duration) -> String {
+    let time_in_seconds = time.as_secs_f32();
+    let only_minutes = time_in_seconds as u32 / 60;
+    let only_seconds = time_in_seconds % 60.0;
+    format!("{:02}:{:04.1}", only_minutes, only_seconds)
+}
+
+/// Adjustments to the level's gameplay parameters, growing as the run goes on.
+pub struct Difficulty {
+    pub level: f32,
+    pub extra_trunks: usize,
+    pub extra_trunks_in_flight: usize,
+    pub woodchip_interval_factor: f32,
+    pub launch_spread_factor: f32,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self {
+            level: 0.0,
+            extra_trunks: 0,
+            extra_trunks_in_flight: 0,
+            woodchip_interval_factor: 1.0,
+            launch_spread_factor: 1.0,
+        }
+    }
+}
+
+/// When set, every level is started with this seed instead of a random one.
+#[derive(Default)]
+pub struct SeedOverride(pub Option<u64>);
+
+/// The source of randomness for all gameplay systems. Reseeded whenever a level is loaded.
+pub struct GameRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self::new(rand::random())
+    }
+}
+
+impl std::ops::Deref for GameRng {
+    type Target = StdRng;
+
+    fn deref(&self) -> &Self::Target {
+        &self.rng
+    }
+}
+
+impl std::ops::DerefMut for GameRng {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.rng
     }
 }
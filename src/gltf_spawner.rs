@@ -1,14 +1,41 @@
+use bevy::asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadedAsset};
 use bevy::ecs::system::{EntityCommands, SystemParam};
-use bevy::gltf::{Gltf, GltfMesh, GltfNode};
+use bevy::gltf::{Gltf, GltfLoader, GltfMesh, GltfNode};
 use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
 use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::render_resource::{PrimitiveTopology, VertexFormat};
+use bevy::utils::HashMap;
 use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
 
-pub struct GltfSpawnerPlugin;
+pub struct GltfSpawnerPlugin {
+    /// When `false`, only the node hierarchy and the colliders are spawned - without the meshes.
+    pub spawn_meshes: bool,
+}
+
+impl Default for GltfSpawnerPlugin {
+    fn default() -> Self {
+        Self { spawn_meshes: true }
+    }
+}
+
+struct GltfSpawnerSettings {
+    spawn_meshes: bool,
+}
 
 impl Plugin for GltfSpawnerPlugin {
     fn build(&self, app: &mut App) {
+        app.insert_resource(GltfSpawnerSettings {
+            spawn_meshes: self.spawn_meshes,
+        });
+        app.add_asset::<GltfNodeInfos>();
+        // Must come after Bevy's `GltfPlugin`, so that it replaces its loader
+        app.init_asset_loader::<GltfWithExtrasLoader>();
         app.add_event::<GltfNodeAddedEvent>();
+        app.add_event::<GltfSpawnError>();
+        app.add_event::<BodyIntersectionEvent>();
+        app.add_event::<BodyContactEvent>();
         app.add_system_to_stage(CoreStage::PostUpdate, spawn_gltf_nodes);
         app.add_system_to_stage(CoreStage::PostUpdate, spawn_colliders);
     }
@@ -19,13 +46,305 @@ pub struct SpawnGltfNode(pub Handle<Gltf>, pub &'static str);
 
 pub struct GltfNodeAddedEvent(pub Entity);
 
-#[derive(Component)]
+/// Builds the colliders of the entity's rigid body from the collider node of a glTF file.
+///
+/// The meshes of the collider node and of its descendants are placed by their transforms relative
+/// to the collider node, and the ones with the same properties are combined into a single collider.
+/// The first collider is inserted to the entity itself. Colliders with other properties are
+/// spawned on child entities attached to the same rigid body - so systems that need the body should
+/// read [`BodyIntersectionEvent`] and [`BodyContactEvent`] rather than Rapier's events.
+#[derive(Component, Default)]
 pub struct SpawnCollider {
     pub gltf: Handle<Gltf>,
     pub node_name: &'static str,
-    pub collider_type: ColliderType,
-    pub material: ColliderMaterial,
-    pub flags: ColliderFlags,
+    /// Overrides the custom properties of the collider node and of its descendants.
+    pub properties: ColliderProperties,
+    /// Used for the properties that neither `properties` nor the custom properties set - so that
+    /// the body still works when the model is exported without them.
+    pub defaults: ColliderProperties,
+}
+
+/// Settings of a collider that can be authored as custom properties (glTF extras) of the collider
+/// node, e.g. in Blender, and overridden by [`SpawnCollider`]. The descendants of the collider node
+/// can set their own properties, and take the rest from the collider node. Properties that are not
+/// set anywhere get Rapier's defaults - except for the density, which defaults to 0.0 so that
+/// colliders do not add mass to rigid bodies that set their mass themselves.
+#[derive(Clone, Default, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ColliderProperties {
+    /// Overrides the shape kind from the collider node's name.
+    pub shape: Option<ColliderShapeKind>,
+    pub sensor: Option<bool>,
+    pub friction: Option<f32>,
+    pub restitution: Option<f32>,
+    /// The mass of the rigid body is derived from this.
+    pub density: Option<f32>,
+    pub contact_events: Option<bool>,
+    pub intersection_events: Option<bool>,
+}
+
+impl ColliderProperties {
+    /// Takes the properties that are set here, and the rest from `defaults`.
+    pub fn or(&self, defaults: &ColliderProperties) -> ColliderProperties {
+        ColliderProperties {
+            shape: self.shape.or(defaults.shape),
+            sensor: self.sensor.or(defaults.sensor),
+            friction: self.friction.or(defaults.friction),
+            restitution: self.restitution.or(defaults.restitution),
+            density: self.density.or(defaults.density),
+            contact_events: self.contact_events.or(defaults.contact_events),
+            intersection_events: self.intersection_events.or(defaults.intersection_events),
+        }
+    }
+
+    fn collider_bundle(&self, shape: SharedShape) -> ColliderBundle {
+        let default_material = ColliderMaterial::default();
+        let mut active_events = ActiveEvents::empty();
+        active_events.set(
+            ActiveEvents::CONTACT_EVENTS,
+            self.contact_events.unwrap_or(false),
+        );
+        active_events.set(
+            ActiveEvents::INTERSECTION_EVENTS,
+            self.intersection_events.unwrap_or(false),
+        );
+        ColliderBundle {
+            shape: shape.into(),
+            collider_type: if self.sensor.unwrap_or(false) {
+                ColliderType::Sensor
+            } else {
+                ColliderType::Solid
+            }
+            .into(),
+            material: ColliderMaterial {
+                friction: self.friction.unwrap_or(default_material.friction),
+                restitution: self.restitution.unwrap_or(default_material.restitution),
+                ..default_material
+            }
+            .into(),
+            flags: ColliderFlags {
+                active_events,
+                ..Default::default()
+            }
+            .into(),
+            mass_properties: ColliderMassProps::Density(self.density.unwrap_or(0.0)).into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// How the collider is built from the collider node's mesh. Chosen by the node's name - the
+/// `node_name` of [`SpawnCollider`] followed by a dot and the shape kind's name (e.g.
+/// `Collider.hull`). A node with the plain `node_name` is a triangle mesh.
+///
+/// The descendants of the collider node get the same shape kind, unless their custom properties
+/// set a different one.
+///
+/// In custom properties, the shape kind is written by the same name as in the node name suffix.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ColliderShapeKind {
+    /// The mesh itself. Only suitable for static bodies and sensors.
+    TriMesh,
+    /// The smallest convex polygon that contains the mesh.
+    ConvexHull,
+    /// Convex polygons that together cover the outline of the mesh.
+    ConvexDecomposition,
+    /// The bounding box of the mesh.
+    Cuboid,
+    /// A capsule fitted to the bounding box of the mesh, along its longer side.
+    Capsule,
+    /// A ball fitted to the bounding box of the mesh.
+    Ball,
+}
+
+impl ColliderShapeKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "trimesh" => ColliderShapeKind::TriMesh,
+            "hull" => ColliderShapeKind::ConvexHull,
+            "convex" => ColliderShapeKind::ConvexDecomposition,
+            "cuboid" => ColliderShapeKind::Cuboid,
+            "capsule" => ColliderShapeKind::Capsule,
+            "ball" => ColliderShapeKind::Ball,
+            _ => return None,
+        })
+    }
+}
+
+impl TryFrom<String> for ColliderShapeKind {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        ColliderShapeKind::from_name(&name).ok_or_else(|| unknown_collider_shape_message(&name))
+    }
+}
+
+fn unknown_collider_shape_message(name: &str) -> String {
+    format!(
+        "unknown collider shape {:?} - expected one of trimesh, hull, convex, cuboid, capsule or ball",
+        name
+    )
+}
+
+/// The names and the custom properties (extras) of the nodes of a glTF file, by node index. Bevy's
+/// glTF loader does not load them, so [`GltfWithExtrasLoader`] adds them to every glTF file as a
+/// labeled asset.
+#[derive(TypeUuid, Debug)]
+#[uuid = "5d1b3c6e-2a7f-4f0e-9c41-8e6a2b7d9f13"]
+pub struct GltfNodeInfos(pub Vec<GltfNodeInfo>);
+
+#[derive(Debug)]
+pub struct GltfNodeInfo {
+    pub name: Option<String>,
+    /// As JSON.
+    pub extras: Option<String>,
+    /// Node indices, in the same order as the `children` of Bevy's `GltfNode`.
+    pub children: Vec<usize>,
+}
+
+const NODE_INFOS_LABEL: &str = "NodeInfos";
+
+/// Bevy's glTF loader, plus [`GltfNodeInfos`].
+#[derive(Default)]
+pub struct GltfWithExtrasLoader(GltfLoader);
+
+impl AssetLoader for GltfWithExtrasLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            self.0.load(bytes, load_context).await?;
+            let gltf = ::gltf::Gltf::from_slice(bytes)?;
+            let node_infos = gltf
+                .nodes()
+                .map(|node| GltfNodeInfo {
+                    name: node.name().map(str::to_owned),
+                    extras: node.extras().as_ref().map(|extras| extras.get().to_owned()),
+                    children: node.children().map(|child| child.index()).collect(),
+                })
+                .collect();
+            load_context.set_labeled_asset(
+                NODE_INFOS_LABEL,
+                LoadedAsset::new(GltfNodeInfos(node_infos)),
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.0.extensions()
+    }
+}
+
+/// Sent (and logged) when a node or a collider could not be spawned from a glTF file. The
+/// entity is tagged with [`GltfSpawnFailed`] instead of getting the node or the collider.
+#[derive(Debug, Clone)]
+pub struct GltfSpawnError {
+    pub entity: Entity,
+    pub asset_path: String,
+    pub node_name: &'static str,
+    pub kind: GltfSpawnErrorKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum GltfSpawnErrorKind {
+    NodeNotFound,
+    MeshNotFound,
+    /// Neither the collider node nor its descendants have a mesh.
+    ColliderNodeHasNoMesh,
+    ColliderNodeHasMaterial,
+    /// More than one node matches the collider node name, with or without a shape kind suffix.
+    MultipleColliderNodes(Vec<String>),
+    /// Collider nodes must have exactly one primitive.
+    ColliderNodePrimitives(usize),
+    InvalidColliderMesh(String),
+    UnknownColliderShape(String),
+    InvalidColliderProperties(String),
+    /// The mesh is too flat or too small for the shape.
+    DegenerateColliderShape(ColliderShapeKind),
+}
+
+impl std::fmt::Display for GltfSpawnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Unable to spawn node {:?} from {}: ",
+            self.node_name, self.asset_path
+        )?;
+        match &self.kind {
+            GltfSpawnErrorKind::NodeNotFound => write!(f, "no such node"),
+            GltfSpawnErrorKind::MeshNotFound => write!(f, "mesh is missing"),
+            GltfSpawnErrorKind::ColliderNodeHasNoMesh => {
+                write!(f, "collider node or its children must have a mesh")
+            }
+            GltfSpawnErrorKind::ColliderNodeHasMaterial => {
+                write!(f, "collider node must not have materials")
+            }
+            GltfSpawnErrorKind::MultipleColliderNodes(names) => {
+                write!(
+                    f,
+                    "only one collider node is allowed, but found {:?}",
+                    names
+                )
+            }
+            GltfSpawnErrorKind::ColliderNodePrimitives(num_primitives) => write!(
+                f,
+                "collider node must have exactly one primitive, but it has {}",
+                num_primitives
+            ),
+            GltfSpawnErrorKind::InvalidColliderMesh(reason) => write!(f, "{}", reason),
+            GltfSpawnErrorKind::UnknownColliderShape(name) => {
+                write!(f, "{}", unknown_collider_shape_message(name))
+            }
+            GltfSpawnErrorKind::InvalidColliderProperties(reason) => {
+                write!(
+                    f,
+                    "invalid custom properties on the collider node: {}",
+                    reason
+                )
+            }
+            GltfSpawnErrorKind::DegenerateColliderShape(kind) => {
+                write!(f, "unable to fit a {:?} collider to the mesh", kind)
+            }
+        }
+    }
+}
+
+/// Tags entities whose [`SpawnGltfNode`] or [`SpawnCollider`] failed.
+#[derive(Component, Debug)]
+pub struct GltfSpawnFailed(pub GltfSpawnErrorKind);
+
+#[derive(SystemParam)]
+struct ErrorReporter<'w, 's> {
+    asset_server: Res<'w, AssetServer>,
+    writer: EventWriter<'w, 's, GltfSpawnError>,
+}
+
+impl<'w, 's> ErrorReporter<'w, 's> {
+    fn report(
+        &mut self,
+        cmd: &mut EntityCommands,
+        gltf: &Handle<Gltf>,
+        node_name: &'static str,
+        kind: GltfSpawnErrorKind,
+    ) {
+        let error = GltfSpawnError {
+            entity: cmd.id(),
+            asset_path: self
+                .asset_server
+                .get_handle_path(gltf)
+                .map(|asset_path| asset_path.path().display().to_string())
+                .unwrap_or_else(|| "unknown asset".to_owned()),
+            node_name,
+            kind,
+        };
+        error!("{}", error);
+        cmd.insert(GltfSpawnFailed(error.kind.clone()));
+        self.writer.send(error);
+    }
 }
 
 fn spawn_gltf_nodes(
@@ -34,24 +353,38 @@ fn spawn_gltf_nodes(
     gltfs: Res<Assets<Gltf>>,
     spawner: Spawner,
     mut event_writer: EventWriter<GltfNodeAddedEvent>,
+    mut error_reporter: ErrorReporter,
 ) {
-    for (entity, SpawnGltfNode(gltf, node_name), orig_transform) in query.iter() {
-        let gltf = if let Some(gltf) = gltfs.get(gltf) {
+    for (entity, SpawnGltfNode(gltf_handle, node_name), orig_transform) in query.iter() {
+        let gltf = if let Some(gltf) = gltfs.get(gltf_handle) {
             gltf
         } else {
             continue;
         };
-        let gltf_node = &gltf.named_nodes[*node_name];
-        let gltf_node = spawner.gltf_nodes.get(gltf_node).unwrap();
         let mut cmd = commands.entity(entity);
         cmd.remove::<SpawnGltfNode>();
-        spawner.spawn_node_recursive(gltf_node, &mut cmd, orig_transform);
-        event_writer.send(GltfNodeAddedEvent(entity));
+        let result = gltf
+            .named_nodes
+            .get(*node_name)
+            .and_then(|gltf_node| spawner.gltf_nodes.get(gltf_node))
+            .ok_or(GltfSpawnErrorKind::NodeNotFound)
+            .and_then(|gltf_node| {
+                spawner.spawn_node_recursive(gltf_node, &mut cmd, orig_transform)
+            });
+        match result {
+            Ok(()) => {
+                event_writer.send(GltfNodeAddedEvent(entity));
+            }
+            Err(kind) => {
+                error_reporter.report(&mut cmd, gltf_handle, *node_name, kind);
+            }
+        }
     }
 }
 
 #[derive(SystemParam)]
 struct Spawner<'w, 's> {
+    settings: Res<'w, GltfSpawnerSettings>,
     gltf_nodes: Res<'w, Assets<GltfNode>>,
     gltf_meshes: Res<'w, Assets<GltfMesh>>,
     #[system_param(ignore)]
@@ -59,38 +392,51 @@ struct Spawner<'w, 's> {
 }
 
 impl<'w, 's> Spawner<'w, 's> {
+    /// Keeps spawning the rest of the hierarchy after an error, and returns the first error.
     fn spawn_node_recursive(
         &self,
         gltf_node: &GltfNode,
         cmd: &mut EntityCommands,
         orig_transform: Option<&Transform>,
-    ) {
+    ) -> Result<(), GltfSpawnErrorKind> {
+        let mut result = Ok(());
         if let Some(orig_transform) = orig_transform {
             cmd.insert(*orig_transform * gltf_node.transform);
         } else {
             cmd.insert(gltf_node.transform);
         }
-        if let Some(mesh) = &gltf_node.mesh {
-            let mesh = self.gltf_meshes.get(mesh).unwrap();
-            cmd.with_children(|commands| {
-                for primitive in mesh.primitives.iter() {
-                    commands.spawn_bundle(PbrBundle {
-                        mesh: primitive.mesh.clone(),
-                        material: primitive.material.clone().unwrap_or_default(),
-                        ..Default::default()
-                    });
-                }
-            });
+        if let Some(mesh) = gltf_node
+            .mesh
+            .as_ref()
+            .filter(|_| self.settings.spawn_meshes)
+        {
+            if let Some(mesh) = self.gltf_meshes.get(mesh) {
+                cmd.with_children(|commands| {
+                    for primitive in mesh.primitives.iter() {
+                        commands.spawn_bundle(PbrBundle {
+                            mesh: primitive.mesh.clone(),
+                            material: primitive.material.clone().unwrap_or_default(),
+                            ..Default::default()
+                        });
+                    }
+                });
+            } else {
+                result = Err(GltfSpawnErrorKind::MeshNotFound);
+            }
         }
         if !gltf_node.children.is_empty() {
             cmd.with_children(|commands| {
                 for child_node in gltf_node.children.iter() {
                     let mut cmd = commands.spawn();
                     cmd.insert(GlobalTransform::identity());
-                    self.spawn_node_recursive(child_node, &mut cmd, None);
+                    let child_result = self.spawn_node_recursive(child_node, &mut cmd, None);
+                    if result.is_ok() {
+                        result = child_result;
+                    }
                 }
             });
         }
+        result
     }
 }
 
@@ -99,83 +445,560 @@ fn spawn_colliders(
     query: Query<(Entity, &SpawnCollider)>,
     gltfs: Res<Assets<Gltf>>,
     gltf_nodes: Res<Assets<GltfNode>>,
+    gltf_node_infos: Res<Assets<GltfNodeInfos>>,
     gltf_meshes: Res<Assets<GltfMesh>>,
     meshes: Res<Assets<Mesh>>,
+    mut error_reporter: ErrorReporter,
 ) {
     for (
         entity,
         SpawnCollider {
-            gltf,
+            gltf: gltf_handle,
             node_name,
-            collider_type,
-            material,
-            flags,
+            properties,
+            defaults,
         },
     ) in query.iter()
     {
-        let gltf = if let Some(gltf) = gltfs.get(gltf) {
+        let gltf = if let Some(gltf) = gltfs.get(gltf_handle) {
             gltf
         } else {
             continue;
         };
-        let gltf_node = &gltf.named_nodes[*node_name];
-        let gltf_node = gltf_nodes.get(gltf_node).unwrap();
-        assert!(
-            gltf_node.children.is_empty(),
-            "Collider node {:?} must not have children",
-            node_name
-        );
         let mut cmd = commands.entity(entity);
         cmd.remove::<SpawnCollider>();
-        let mesh = gltf_node
-            .mesh
-            .as_ref()
-            .expect("Collider node must have a mesh");
-        let mesh = gltf_meshes.get(mesh).unwrap();
-        let mut it = mesh.primitives.iter();
-        let primitive = it.next().expect("Collider node has no primitives");
-        assert!(
-            it.next().is_none(),
-            "Collider node has more than on primitive"
-        );
-        assert!(
-            primitive.material.is_none(),
-            "Collider node {:?} must not have materials",
-            node_name
-        );
-        let mesh = meshes.get(&primitive.mesh).unwrap();
-        let tri_mesh = TriMesh::new(
-            {
-                if let VertexAttributeValues::Float32x3(vertices) =
-                    mesh.attribute(Mesh::ATTRIBUTE_POSITION).unwrap()
-                {
-                    vertices.iter().map(|&[x, y, _]| point![x, y]).collect()
-                } else {
-                    panic!("Not Float32x3")
+        let node_infos = error_reporter
+            .asset_server
+            .get_handle_path(gltf_handle)
+            .and_then(|asset_path| {
+                gltf_node_infos.get(AssetPath::new_ref(
+                    asset_path.path(),
+                    Some(NODE_INFOS_LABEL),
+                ))
+            });
+        let result = find_collider_node(gltf, node_name, &gltf_nodes).and_then(
+            |(collider_node_name, gltf_node, kind_from_name)| {
+                let node_index = node_infos.and_then(|node_infos| {
+                    node_infos
+                        .0
+                        .iter()
+                        .position(|info| info.name.as_deref() == Some(collider_node_name))
+                });
+                let mut collider_meshes = Vec::new();
+                // The transform of the collider node itself is not used - the rigid body places it
+                collect_collider_meshes(
+                    gltf_node,
+                    node_infos.zip(node_index),
+                    Transform::identity(),
+                    &gltf_meshes,
+                    &meshes,
+                    &mut collider_meshes,
+                )?;
+                if collider_meshes.is_empty() {
+                    return Err(GltfSpawnErrorKind::ColliderNodeHasNoMesh);
                 }
-            },
-            {
-                let mut triangles = Vec::new();
-                match mesh.indices().unwrap() {
-                    Indices::U16(_indices) => {}
-                    Indices::U32(indices) => {
-                        for i in 0..indices.len() {
-                            if i % 3 == 0 {
-                                triangles.push([indices[i], indices[i + 1], indices[i + 2]]);
-                            }
-                        }
-                    }
+                let collider_node_properties =
+                    properties.or(&node_properties(node_infos.zip(node_index))?);
+                // Meshes of different shape kinds can still share a collider
+                let mut groups = Vec::<(ColliderProperties, ColliderShapeBuilder)>::new();
+                for collider_mesh in collider_meshes {
+                    let properties = properties
+                        .or(&collider_mesh.properties)
+                        .or(&collider_node_properties)
+                        .or(defaults);
+                    let kind = properties
+                        .shape
+                        .or(kind_from_name)
+                        .unwrap_or(ColliderShapeKind::TriMesh);
+                    let group_properties = ColliderProperties {
+                        shape: None,
+                        ..properties
+                    };
+                    let group_index = if let Some(index) = groups
+                        .iter()
+                        .position(|(properties, _)| *properties == group_properties)
+                    {
+                        index
+                    } else {
+                        groups.push((group_properties, Default::default()));
+                        groups.len() - 1
+                    };
+                    groups[group_index]
+                        .1
+                        .add(&collider_mesh, kind)
+                        .ok_or(GltfSpawnErrorKind::DegenerateColliderShape(kind))?;
                 }
-                triangles
+                Ok(groups
+                    .into_iter()
+                    .flat_map(|(properties, builder)| {
+                        builder
+                            .build()
+                            .map(move |shape| properties.collider_bundle(shape))
+                    })
+                    .collect::<Vec<_>>())
             },
         );
-        cmd.insert_bundle(ColliderBundle {
-            shape: SharedShape::new(tri_mesh).into(),
-            collider_type: (*collider_type).into(),
-            material: (*material).into(),
-            flags: (*flags).into(),
-            mass_properties: ColliderMassProps::Density(0.0).into(),
-            ..Default::default()
+        match result {
+            Ok(mut collider_bundles) => {
+                // There is always at least one, since there is at least one mesh
+                let child_collider_bundles = collider_bundles.split_off(1);
+                for collider_bundle in collider_bundles {
+                    cmd.insert_bundle(collider_bundle);
+                }
+                if !child_collider_bundles.is_empty() {
+                    cmd.with_children(|commands| {
+                        for collider_bundle in child_collider_bundles {
+                            commands
+                                .spawn_bundle(collider_bundle)
+                                .insert(ColliderParentComponent(ColliderParent {
+                                    handle: entity.handle(),
+                                    // The shapes are already placed relative to the collider node
+                                    pos_wrt_parent: Isometry::identity(),
+                                }));
+                        }
+                    });
+                }
+            }
+            Err(kind) => {
+                error_reporter.report(&mut cmd, gltf_handle, *node_name, kind);
+            }
+        }
+    }
+}
+
+/// A Rapier [`IntersectionEvent`], with the colliders resolved to the entities of their rigid
+/// bodies.
+#[derive(Debug, Clone, Copy)]
+pub struct BodyIntersectionEvent {
+    pub body1: Entity,
+    pub body2: Entity,
+    pub intersecting: bool,
+}
+
+/// A Rapier [`ContactEvent`], with the colliders resolved to the entities of their rigid bodies.
+#[derive(Debug, Clone, Copy)]
+pub enum BodyContactEvent {
+    Started(Entity, Entity),
+    Stopped(Entity, Entity),
+}
+
+/// The entity of the rigid body the collider is attached to. Falls back to the collider's own
+/// entity when it is not attached yet, or already despawned.
+pub fn collider_body_entity(
+    collider: ColliderHandle,
+    collider_parents: &Query<&ColliderParentComponent>,
+) -> Entity {
+    if let Ok(collider_parent) = collider_parents.get(collider.entity()) {
+        collider_parent.handle.entity()
+    } else {
+        collider.entity()
+    }
+}
+
+/// Sends a [`BodyIntersectionEvent`] for every [`IntersectionEvent`] and a [`BodyContactEvent`]
+/// for every [`ContactEvent`]. Must run after the physics step and before the systems that read
+/// them.
+pub fn resolve_collider_events(
+    mut intersection_reader: EventReader<IntersectionEvent>,
+    mut contact_reader: EventReader<ContactEvent>,
+    collider_parents: Query<&ColliderParentComponent>,
+    mut intersection_writer: EventWriter<BodyIntersectionEvent>,
+    mut contact_writer: EventWriter<BodyContactEvent>,
+) {
+    let body = |collider| collider_body_entity(collider, &collider_parents);
+    for event in intersection_reader.iter() {
+        intersection_writer.send(BodyIntersectionEvent {
+            body1: body(event.collider1),
+            body2: body(event.collider2),
+            intersecting: event.intersecting,
         });
     }
+    for event in contact_reader.iter() {
+        contact_writer.send(match *event {
+            ContactEvent::Started(handle1, handle2) => {
+                BodyContactEvent::Started(body(handle1), body(handle2))
+            }
+            ContactEvent::Stopped(handle1, handle2) => {
+                BodyContactEvent::Stopped(body(handle1), body(handle2))
+            }
+        });
+    }
+}
+
+/// Finds the collider node - either named exactly `node_name` or with a shape kind suffix. Returns
+/// the actual name of the node and the shape kind from the suffix.
+fn find_collider_node<'a>(
+    gltf: &'a Gltf,
+    node_name: &str,
+    gltf_nodes: &'a Assets<GltfNode>,
+) -> Result<(&'a str, &'a GltfNode, Option<ColliderShapeKind>), GltfSpawnErrorKind> {
+    let prefix = format!("{}.", node_name);
+    let matches: Vec<(&String, &Handle<GltfNode>)> = gltf
+        .named_nodes
+        .iter()
+        .filter(|(name, _)| *name == node_name || name.starts_with(&prefix))
+        .collect();
+    let (name, gltf_node) = match matches.as_slice() {
+        [] => return Err(GltfSpawnErrorKind::NodeNotFound),
+        [single_match] => *single_match,
+        _ => {
+            let mut names: Vec<String> = matches.iter().map(|(name, _)| (*name).clone()).collect();
+            names.sort();
+            return Err(GltfSpawnErrorKind::MultipleColliderNodes(names));
+        }
+    };
+    let kind = if name == node_name {
+        None
+    } else {
+        let kind_name = &name[prefix.len()..];
+        Some(
+            ColliderShapeKind::from_name(kind_name)
+                .ok_or_else(|| GltfSpawnErrorKind::UnknownColliderShape(kind_name.to_owned()))?,
+        )
+    };
+    let gltf_node = gltf_nodes
+        .get(gltf_node)
+        .ok_or(GltfSpawnErrorKind::NodeNotFound)?;
+    Ok((name.as_str(), gltf_node, kind))
+}
+
+/// The custom properties of a node. Without [`GltfNodeInfos`], no properties are set.
+fn node_properties(
+    node: Option<(&GltfNodeInfos, usize)>,
+) -> Result<ColliderProperties, GltfSpawnErrorKind> {
+    let extras = node.and_then(|(node_infos, index)| node_infos.0.get(index)?.extras.as_ref());
+    if let Some(extras) = extras {
+        serde_json::from_str(extras)
+            .map_err(|err| GltfSpawnErrorKind::InvalidColliderProperties(err.to_string()))
+    } else {
+        Ok(Default::default())
+    }
+}
+
+/// A mesh of the collider node or of one of its descendants.
+struct ColliderMesh {
+    /// Relative to the collider node.
+    transform: Transform,
+    vertices: Vec<Point<Real>>,
+    triangles: Vec<[u32; 3]>,
+    /// The custom properties of the node the mesh belongs to.
+    properties: ColliderProperties,
+}
+
+/// `node` is the node's index in the [`GltfNodeInfos`], when available.
+fn collect_collider_meshes(
+    gltf_node: &GltfNode,
+    node: Option<(&GltfNodeInfos, usize)>,
+    transform: Transform,
+    gltf_meshes: &Assets<GltfMesh>,
+    meshes: &Assets<Mesh>,
+    collider_meshes: &mut Vec<ColliderMesh>,
+) -> Result<(), GltfSpawnErrorKind> {
+    if let Some(mesh) = gltf_node.mesh.as_ref() {
+        let mesh = gltf_meshes
+            .get(mesh)
+            .ok_or(GltfSpawnErrorKind::MeshNotFound)?;
+        let primitive = match mesh.primitives.as_slice() {
+            [primitive] => primitive,
+            primitives => {
+                return Err(GltfSpawnErrorKind::ColliderNodePrimitives(primitives.len()));
+            }
+        };
+        if primitive.material.is_some() {
+            return Err(GltfSpawnErrorKind::ColliderNodeHasMaterial);
+        }
+        let mesh = meshes
+            .get(&primitive.mesh)
+            .ok_or(GltfSpawnErrorKind::MeshNotFound)?;
+        let (vertices, triangles) =
+            collider_triangles(mesh).map_err(GltfSpawnErrorKind::InvalidColliderMesh)?;
+        collider_meshes.push(ColliderMesh {
+            transform,
+            vertices,
+            triangles,
+            properties: node_properties(node)?,
+        });
+    }
+    for (child_index, child_node) in gltf_node.children.iter().enumerate() {
+        let child = node.and_then(|(node_infos, index)| {
+            Some((
+                node_infos,
+                *node_infos.0.get(index)?.children.get(child_index)?,
+            ))
+        });
+        collect_collider_meshes(
+            child_node,
+            child,
+            transform * child_node.transform,
+            gltf_meshes,
+            meshes,
+            collider_meshes,
+        )?;
+    }
+    Ok(())
+}
+
+/// Combines the meshes of a collider into shapes. Compound shapes cannot contain triangle meshes,
+/// so the triangle meshes are merged into one triangle mesh and the other kinds into one compound
+/// shape - a collider that has both kinds is split into two.
+#[derive(Default)]
+struct ColliderShapeBuilder {
+    convex_parts: Vec<(Isometry<Real>, SharedShape)>,
+    trimesh_vertices: Vec<Point<Real>>,
+    trimesh_triangles: Vec<[u32; 3]>,
+}
+
+impl ColliderShapeBuilder {
+    /// `None` if the shape cannot be fitted to the mesh.
+    fn add(&mut self, collider_mesh: &ColliderMesh, kind: ColliderShapeKind) -> Option<()> {
+        if kind == ColliderShapeKind::TriMesh {
+            let first_index = self.trimesh_vertices.len() as u32;
+            self.trimesh_vertices
+                .extend(collider_mesh.placed_vertices());
+            self.trimesh_triangles.extend(
+                collider_mesh
+                    .triangles
+                    .iter()
+                    .map(|triangle| triangle.map(|index| first_index + index)),
+            );
+        } else {
+            self.convex_parts.extend(collider_mesh.convex_parts(kind)?);
+        }
+        Some(())
+    }
+
+    /// The shapes, relative to the collider node.
+    fn build(self) -> impl Iterator<Item = SharedShape> {
+        let trimesh = if self.trimesh_triangles.is_empty() {
+            None
+        } else {
+            Some(SharedShape::trimesh(
+                self.trimesh_vertices,
+                self.trimesh_triangles,
+            ))
+        };
+        let compound = match self.convex_parts.as_slice() {
+            [] => None,
+            [(position, shape)] if *position == Isometry::identity() => Some(shape.clone()),
+            _ => Some(SharedShape::compound(self.convex_parts)),
+        };
+        trimesh.into_iter().chain(compound)
+    }
+}
+
+impl ColliderMesh {
+    /// The position of the mesh, without its scale. Rotations that leave the XY plane are ignored.
+    fn isometry(&self) -> Isometry<Real> {
+        let direction = self.transform.rotation * Vec3::X;
+        Isometry::new(
+            vector![self.transform.translation.x, self.transform.translation.y],
+            direction.y.atan2(direction.x),
+        )
+    }
+
+    fn scaled_vertices(&self) -> Vec<Point<Real>> {
+        let scale = self.transform.scale;
+        self.vertices
+            .iter()
+            .map(|vertex| point![vertex.x * scale.x, vertex.y * scale.y])
+            .collect()
+    }
+
+    /// The vertices relative to the collider node.
+    fn placed_vertices(&self) -> Vec<Point<Real>> {
+        let isometry = self.isometry();
+        self.scaled_vertices()
+            .into_iter()
+            .map(|vertex| isometry * vertex)
+            .collect()
+    }
+
+    /// The convex shapes that make up the mesh, each placed by the mesh's transform relative to the
+    /// collider node. `None` if the shape cannot be fitted to the mesh - and for
+    /// [`ColliderShapeKind::TriMesh`], which is not convex.
+    fn convex_parts(&self, kind: ColliderShapeKind) -> Option<Vec<(Isometry<Real>, SharedShape)>> {
+        let isometry = self.isometry();
+        // Primitive shapes are centered on the origin, so they need to be moved to where the mesh
+        // is
+        let fitted = |shape_for_half_extents: fn(Vector<Real>) -> SharedShape| {
+            let vertices = self.scaled_vertices();
+            let mut mins = *vertices.first()?;
+            let mut maxs = mins;
+            for vertex in vertices.iter() {
+                mins = mins.inf(vertex);
+                maxs = maxs.sup(vertex);
+            }
+            let center: Vector<Real> = (mins.coords + maxs.coords) / 2.0;
+            let half_extents = (maxs - mins) / 2.0;
+            if half_extents.x <= 0.0 || half_extents.y <= 0.0 {
+                return None;
+            }
+            Some(vec![(
+                isometry * Isometry::translation(center.x, center.y),
+                shape_for_half_extents(half_extents),
+            )])
+        };
+        match kind {
+            ColliderShapeKind::TriMesh => None,
+            ColliderShapeKind::ConvexHull => Some(vec![(
+                isometry,
+                SharedShape::convex_hull(&self.scaled_vertices())?,
+            )]),
+            ColliderShapeKind::ConvexDecomposition => {
+                let vertices = self.scaled_vertices();
+                let outline = outline_segments(&vertices, &self.triangles);
+                if outline.is_empty() {
+                    return None;
+                }
+                let shape = SharedShape::convex_decomposition(&vertices, &outline);
+                Some(
+                    shape
+                        .as_compound()?
+                        .shapes()
+                        .iter()
+                        .map(|(position, part)| (isometry * position, part.clone()))
+                        .collect(),
+                )
+            }
+            ColliderShapeKind::Cuboid => {
+                fitted(|half_extents| SharedShape::cuboid(half_extents.x, half_extents.y))
+            }
+            ColliderShapeKind::Capsule => fitted(|half_extents| {
+                if half_extents.x < half_extents.y {
+                    let half_segment = half_extents.y - half_extents.x;
+                    SharedShape::capsule(
+                        point![0.0, -half_segment],
+                        point![0.0, half_segment],
+                        half_extents.x,
+                    )
+                } else {
+                    let half_segment = half_extents.x - half_extents.y;
+                    SharedShape::capsule(
+                        point![-half_segment, 0.0],
+                        point![half_segment, 0.0],
+                        half_extents.y,
+                    )
+                }
+            }),
+            ColliderShapeKind::Ball => {
+                fitted(|half_extents| SharedShape::ball(half_extents.x.max(half_extents.y)))
+            }
+        }
+    }
+}
+
+/// The edges that belong to only one triangle, as pairs of indices into `vertices`.
+///
+/// glTF exporters split vertices along UV and normal seams, so vertices at the same 2D position are
+/// merged first - otherwise the seams would count as outline edges. The segments use the first
+/// index of each merged vertex.
+pub fn outline_segments(vertices: &[Point<Real>], triangles: &[[u32; 3]]) -> Vec<[u32; 2]> {
+    let mut first_index_by_position = HashMap::<[u32; 2], u32>::default();
+    let merged_indices: Vec<u32> = vertices
+        .iter()
+        .enumerate()
+        .map(|(index, vertex)| {
+            // Adding zero turns -0.0 into 0.0, so that both have the same bits
+            let position = [(vertex.x + 0.0).to_bits(), (vertex.y + 0.0).to_bits()];
+            *first_index_by_position
+                .entry(position)
+                .or_insert(index as u32)
+        })
+        .collect();
+    let merged_edges = |&[a, b, c]: &[u32; 3]| {
+        let [a, b, c] = [a, b, c].map(|index| merged_indices[index as usize]);
+        [(a, b), (b, c), (c, a)]
+    };
+    let mut edge_counts = HashMap::<[u32; 2], usize>::default();
+    for triangle in triangles {
+        for (from, to) in merged_edges(triangle) {
+            *edge_counts.entry([from.min(to), from.max(to)]).or_default() += 1;
+        }
+    }
+    // Keep the winding of the triangles, and a deterministic order
+    let mut segments = Vec::new();
+    for triangle in triangles {
+        for (from, to) in merged_edges(triangle) {
+            if edge_counts[&[from.min(to), from.max(to)]] == 1 {
+                segments.push([from, to]);
+            }
+        }
+    }
+    segments
+}
+
+/// The vertices (flattened to 2D) and the triangles of a collider mesh.
+fn collider_triangles(mesh: &Mesh) -> Result<(Vec<Point<Real>>, Vec<[u32; 3]>), String> {
+    let vertices: Vec<Point<Real>> = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        Some(VertexAttributeValues::Float32x3(vertices)) => {
+            vertices.iter().map(|&[x, y, _]| point![x, y]).collect()
+        }
+        Some(VertexAttributeValues::Float32x2(vertices)) => {
+            vertices.iter().map(|&[x, y]| point![x, y]).collect()
+        }
+        Some(other) => {
+            return Err(format!(
+                "unsupported vertex position format {:?}",
+                VertexFormat::from(other)
+            ));
+        }
+        None => {
+            return Err("mesh has no vertex positions".to_owned());
+        }
+    };
+    // Meshes without an index buffer use each vertex once, in order
+    let indices: Vec<u32> = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|&index| index as u32).collect(),
+        Some(Indices::U32(indices)) => indices.clone(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+    if let Some(index) = indices
+        .iter()
+        .find(|&&index| vertices.len() <= index as usize)
+    {
+        return Err(format!(
+            "index {} is out of range for {} vertices",
+            index,
+            vertices.len()
+        ));
+    }
+    let triangles: Vec<[u32; 3]> = match mesh.primitive_topology() {
+        PrimitiveTopology::TriangleList => {
+            if indices.len() % 3 != 0 {
+                return Err(format!(
+                    "triangle list has {} indices, which is not a multiple of 3",
+                    indices.len()
+                ));
+            }
+            indices
+                .chunks_exact(3)
+                .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+                .collect()
+        }
+        PrimitiveTopology::TriangleStrip => indices
+            .windows(3)
+            .enumerate()
+            // Every other triangle in a strip is flipped, so flip it back to keep the winding
+            .map(|(i, triangle)| {
+                if i % 2 == 0 {
+                    [triangle[0], triangle[1], triangle[2]]
+                } else {
+                    [triangle[1], triangle[0], triangle[2]]
+                }
+            })
+            // Strips use degenerate triangles to connect their parts
+            .filter(|[a, b, c]| a != b && b != c && a != c)
+            .collect(),
+        topology => {
+            return Err(format!(
+                "unsupported primitive topology {:?} - collider meshes must be made of triangles",
+                topology
+            ));
+        }
+    };
+    // Parry asserts that triangle meshes are not empty
+    if triangles.is_empty() {
+        return Err("mesh has no triangles".to_owned());
+    }
+    Ok((vertices, triangles))
 }
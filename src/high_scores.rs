@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::global_types::{format_time, AppState, GameMode, MenuState, ScoreFormula, ScoreStatus};
+use crate::level::LevelSelection;
+use crate::persistence;
+use crate::replay::ReplaySettings;
+
+pub struct HighScoresPlugin;
+
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(persistence::load::<HighScores>(STORAGE_KEY).unwrap_or_default());
+        app.add_system_set(
+            SystemSet::on_enter(AppState::Menu(MenuState::GameOver)).with_system(offer_high_score),
+        );
+        app.add_system_set(
+            SystemSet::on_exit(AppState::Menu(MenuState::GameOver))
+                .with_system(discard_pending_high_score),
+        );
+    }
+}
+
+const STORAGE_KEY: &str = "high-scores";
+pub const MAX_HIGH_SCORES: usize = 10;
+
+/// The best endless single player runs, best first.
+#[derive(Default, Serialize, Deserialize)]
+pub struct HighScores {
+    pub entries: Vec<HighScoreEntry>,
+    /// The name entered last time, to prefill the name entry with.
+    pub last_name: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HighScoreEntry {
+    pub name: String,
+    #[serde(default)]
+    pub score: u64,
+    pub time: Duration,
+    pub logs_chipped: u32,
+    pub woodchips_cleared: u32,
+}
+
+impl HighScoreEntry {
+    fn rank_key(&self) -> (u64, Duration) {
+        (self.score, self.time)
+    }
+
+    pub fn format_time(&self) -> String {
+        format_time(self.time)
+    }
+}
+
+impl HighScores {
+    /// Where the entry would be placed in the table, or `None` if it does not make it.
+    pub fn position_for(&self, entry: &HighScoreEntry) -> Option<usize> {
+        let position = self
+            .entries
+            .iter()
+            .position(|existing| existing.rank_key() < entry.rank_key())
+            .unwrap_or(self.entries.len());
+        if position < MAX_HIGH_SCORES {
+            Some(position)
+        } else {
+            None
+        }
+    }
+
+    /// Add the entry to the table and save the table. Returns the entry's position.
+    pub fn insert(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        let position = self.position_for(&entry)?;
+        self.last_name = entry.name.clone();
+        self.entries.insert(position, entry);
+        self.entries.truncate(MAX_HIGH_SCORES);
+        persistence::save(STORAGE_KEY, self);
+        Some(position)
+    }
+}
+
+/// The best run in the high score table before the current one was added. Only set on the game
+/// over menu, for single player endless runs that are not replays - whether or not they make it
+/// into the table.
+pub struct PreviousPersonalBest(pub Option<HighScoreEntry>);
+
+/// A run that made it into the high score table, waiting for the player to enter their name.
+pub struct PendingHighScore {
+    pub entry: HighScoreEntry,
+    /// Set once the player has entered their name and the entry was added to the table.
+    pub saved_at: Option<usize>,
+}
+
+fn offer_high_score(
+    mut commands: Commands,
+    high_scores: Res<HighScores>,
+    score_status: Res<ScoreStatus>,
+    score_formula: Res<ScoreFormula>,
+    game_mode: Res<GameMode>,
+    level_selection: Res<LevelSelection>,
+    replay_settings: Res<ReplaySettings>,
+) {
+    if *game_mode != GameMode::SinglePlayer
+        || *level_selection != LevelSelection::Endless
+        || replay_settings.play_from.is_some()
+    {
+        return;
+    }
+    commands.insert_resource(PreviousPersonalBest(high_scores.entries.first().cloned()));
+    let entry = HighScoreEntry {
+        name: high_scores.last_name.clone(),
+        score: score_status.score(&score_formula),
+        time: score_status.time,
+        logs_chipped: score_status.logs_chipped,
+        woodchips_cleared: score_status.woodchips_cleared,
+    };
+    if high_scores.position_for(&entry).is_some() {
+        commands.insert_resource(PendingHighScore {
+            entry,
+            saved_at: None,
+        });
+    }
+}
+
+fn discard_pending_high_score(mut commands: Commands) {
+    commands.remove_resource::<PendingHighScore>();
+    commands.remove_resource::<PreviousPersonalBest>();
+}
@@ -0,0 +1,249 @@
+use bevy::asset::{BoxedFuture, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::global_types::ScoreStatus;
+use crate::loading::{parse_ron_asset, LevelAssets, RonAsset};
+use crate::persistence;
+
+/// The layout of a level, loaded from a `.level.ron` file.
+#[derive(TypeUuid, Deserialize, Debug, Clone)]
+#[uuid = "a31db64a-4d94-4d20-b614-1e5ffa90b928"]
+pub struct Level {
+    /// Positions of the chippers.
+    pub chippers: Vec<(f32, f32)>,
+    /// Where each player spawns. When there are more players than spawn points, the spawn points
+    /// are reused.
+    pub player_spawns: Vec<(f32, f32)>,
+    pub trunk_launchers: Vec<TrunkLauncher>,
+    /// How many trunks can be in the level at once - both flying and in chippers.
+    pub max_trunks: usize,
+    /// How many trunks can fly toward the chippers at once.
+    pub max_trunks_in_flight: usize,
+    /// How the level gets harder as the run goes on. When not set, the difficulty stays the same.
+    #[serde(default)]
+    pub difficulty_ramp: Option<DifficultyRamp>,
+}
+
+impl RonAsset for Level {
+    const EXTENSIONS: &'static [&'static str] = &["level.ron"];
+
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.player_spawns.is_empty() {
+            anyhow::bail!("level has no player spawns");
+        }
+        if self.trunk_launchers.is_empty() {
+            anyhow::bail!("level has no trunk launchers");
+        }
+        if let Some(ramp) = &self.difficulty_ramp {
+            ramp.validate()?;
+        }
+        Ok(())
+    }
+}
+
+impl Level {
+    pub fn player_spawn(&self, player_index: usize) -> (f32, f32) {
+        self.player_spawns[player_index % self.player_spawns.len()]
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TrunkLauncher {
+    pub position: (f32, f32),
+    pub velocity_x: RandomRange,
+    pub velocity_y: RandomRange,
+    pub angular_velocity: RandomRange,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct RandomRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl RandomRange {
+    pub fn sample(&self, rng: &mut impl Rng) -> f32 {
+        self.min + (self.max - self.min) * rng.gen::<f32>()
+    }
+
+    /// Scale the range around its center.
+    pub fn widened(&self, factor: f32) -> Self {
+        let center = 0.5 * (self.min + self.max);
+        let half_width = 0.5 * (self.max - self.min) * factor;
+        Self {
+            min: center - half_width,
+            max: center + half_width,
+        }
+    }
+}
+
+/// Maps the progress of a run to a difficulty level, and the difficulty level to gameplay
+/// parameters. The difficulty level is a continuous value that starts at zero.
+#[derive(Deserialize, Debug, Clone)]
+pub struct DifficultyRamp {
+    /// Survival time, in seconds, that adds one difficulty level.
+    pub seconds_per_level: f32,
+    /// Number of chipped logs that add one difficulty level.
+    pub logs_per_level: f32,
+    pub max_level: f32,
+    /// Added to [`Level::max_trunks`] for each difficulty level. Rounded down.
+    pub extra_trunks_per_level: f32,
+    /// Added to [`Level::max_trunks_in_flight`] for each difficulty level. Rounded down.
+    pub extra_trunks_in_flight_per_level: f32,
+    /// The time between wood chips is multiplied by this once for each difficulty level.
+    pub woodchip_interval_factor_per_level: f32,
+    /// How much wider the launch velocity ranges get with each difficulty level, relative to
+    /// their original width.
+    pub launch_spread_per_level: f32,
+}
+
+impl DifficultyRamp {
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        for (name, value) in [
+            ("seconds_per_level", self.seconds_per_level),
+            ("logs_per_level", self.logs_per_level),
+            (
+                "woodchip_interval_factor_per_level",
+                self.woodchip_interval_factor_per_level,
+            ),
+        ] {
+            if value.is_nan() || value <= 0.0 {
+                anyhow::bail!("difficulty ramp {} must be positive, not {}", name, value);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The sequence of levels the player goes through, loaded from a `.campaign.ron` file.
+#[derive(TypeUuid, Deserialize, Debug)]
+#[uuid = "e52e5425-27cc-4fe8-a6de-e37346e96c85"]
+pub struct Campaign {
+    pub levels: Vec<CampaignLevel>,
+}
+
+impl RonAsset for Campaign {
+    const EXTENSIONS: &'static [&'static str] = &["campaign.ron"];
+
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.levels.is_empty() {
+            anyhow::bail!("campaign has no levels");
+        }
+        Ok(())
+    }
+
+    fn load_referenced_assets<'a>(
+        &'a mut self,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            for (index, campaign_level) in self.levels.iter_mut().enumerate() {
+                let level_bytes = load_context
+                    .read_asset_bytes(&campaign_level.level_path)
+                    .await?;
+                let level: Level = parse_ron_asset(&level_bytes)
+                    .map_err(|err| anyhow::anyhow!("{}: {}", campaign_level.level_path, err))?;
+                // Embedding the levels in the campaign asset means the campaign is only ready
+                // once all its levels are.
+                campaign_level.level = load_context
+                    .set_labeled_asset(&format!("level{}", index), LoadedAsset::new(level));
+            }
+            Ok(())
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CampaignLevel {
+    pub name: String,
+    /// Path of the `.level.ron` file, relative to the assets directory.
+    #[serde(rename = "level")]
+    pub level_path: String,
+    /// Set when the campaign is loaded.
+    #[serde(skip)]
+    pub level: Handle<Level>,
+    pub goal: LevelGoal,
+}
+
+/// What the player needs to do in a campaign level in order to complete it.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum LevelGoal {
+    ChipLogs(u32),
+    ClearWoodchips(u32),
+    /// In seconds.
+    Survive(f32),
+}
+
+impl LevelGoal {
+    pub fn is_reached(&self, score_status: &ScoreStatus) -> bool {
+        match self {
+            LevelGoal::ChipLogs(logs) => *logs <= score_status.logs_chipped,
+            LevelGoal::ClearWoodchips(woodchips) => *woodchips <= score_status.woodchips_cleared,
+            LevelGoal::Survive(seconds) => *seconds <= score_status.time.as_secs_f32(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            LevelGoal::ChipLogs(logs) => format!("Chip {} logs", logs),
+            LevelGoal::ClearWoodchips(woodchips) => format!("Clear {} wood chips", woodchips),
+            LevelGoal::Survive(seconds) => format!("Survive for {} seconds", seconds),
+        }
+    }
+}
+
+/// Which level to play when the level gets (re)loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LevelSelection {
+    Endless,
+    /// Index into [`Campaign::levels`].
+    Campaign(usize),
+}
+
+impl Default for LevelSelection {
+    fn default() -> Self {
+        Self::Endless
+    }
+}
+
+/// How far the player got in the campaign.
+#[derive(Serialize, Deserialize)]
+pub struct CampaignProgress {
+    /// The number of campaign levels that can be selected. Always at least one.
+    pub unlocked: usize,
+}
+
+impl Default for CampaignProgress {
+    fn default() -> Self {
+        Self { unlocked: 1 }
+    }
+}
+
+const CAMPAIGN_PROGRESS_STORAGE_KEY: &str = "campaign-progress";
+
+impl CampaignProgress {
+    pub fn load() -> Self {
+        let progress: Self = persistence::load(CAMPAIGN_PROGRESS_STORAGE_KEY).unwrap_or_default();
+        Self {
+            unlocked: progress.unlocked.max(1),
+        }
+    }
+
+    pub fn save(&self) {
+        persistence::save(CAMPAIGN_PROGRESS_STORAGE_KEY, self);
+    }
+}
+
+/// The level that `AppState::LoadLevel` will set up. Resolved from the [`LevelSelection`] before
+/// entering that state.
+pub struct CurrentLevel(pub Handle<Level>);
+
+impl FromWorld for CurrentLevel {
+    fn from_world(world: &mut World) -> Self {
+        let level_assets = world.get_resource::<LevelAssets>().unwrap();
+        Self(level_assets.endless.clone())
+    }
+}
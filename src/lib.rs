@@ -1,32 +1,58 @@
+mod achievements;
+mod audio;
+pub mod controls;
 mod game_systems;
 pub mod global_types;
 pub mod gltf_spawner;
+mod high_scores;
+pub mod level;
 mod loading;
 mod menu;
+mod persistence;
+pub mod replay;
 mod score_display;
+pub mod settings;
+mod touch_controls;
 mod utils;
 
 use bevy::app::App;
 #[cfg(debug_assertions)]
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::gltf::GltfPlugin;
 use bevy::prelude::*;
+use bevy::scene::ScenePlugin;
+use bevy::transform::TransformPlugin;
+use bevy_rapier2d::physics::{NoUserData, RapierPhysicsPlugin};
+use bevy_tweening::TweeningPlugin;
 
-use self::game_systems::GameSystemsPlugin;
+use self::achievements::AchievementsPlugin;
+use self::audio::GameAudioPlugin;
+use self::controls::ControlsPlugin;
+use self::game_systems::{GamePresentationPlugin, GameSystemsPlugin};
 use self::global_types::{AppState, MenuState};
 use self::gltf_spawner::GltfSpawnerPlugin;
-use self::loading::LoadingPlugin;
+use self::high_scores::HighScoresPlugin;
+use self::loading::{LoadingPlugin, PresentationLoadingPlugin};
 use self::menu::MenuPlugin;
+use self::replay::ReplayPlugin;
 use self::score_display::ScoreDisplayPlugin;
+use self::settings::SettingsPlugin;
+use self::touch_controls::TouchControlsPlugin;
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_state(AppState::Menu(MenuState::Main));
-        app.add_plugin(LoadingPlugin);
-        app.add_plugin(GltfSpawnerPlugin);
+        add_game_logic(app, GltfSpawnerPlugin::default());
+        app.add_plugin(SettingsPlugin);
+        app.add_plugin(ControlsPlugin);
+        app.add_plugin(TouchControlsPlugin);
+        app.add_plugin(PresentationLoadingPlugin);
         app.add_plugin(MenuPlugin);
-        app.add_plugin(GameSystemsPlugin);
+        app.add_plugin(HighScoresPlugin);
+        app.add_plugin(AchievementsPlugin);
+        app.add_plugin(GamePresentationPlugin);
+        app.add_plugin(GameAudioPlugin);
 
         app.add_startup_system(|mut commands: Commands| {
             commands.spawn_bundle(UiCameraBundle::default());
@@ -44,3 +70,47 @@ impl Plugin for GamePlugin {
         std::any::type_name::<Self>()
     }
 }
+
+/// Runs the game without a window, a GPU, menus, particles or audio.
+///
+/// Unlike [`GamePlugin`], which expects `DefaultPlugins` and the physics plugin to be added by
+/// the binary, this plugin brings in all the engine plugins it needs - so it should be added to
+/// an empty `App`. Nothing advances the `AppState` out of the main menu, so whoever drives the
+/// headless app is responsible for setting it.
+pub struct HeadlessGamePlugin;
+
+impl Plugin for HeadlessGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MinimalPlugins);
+        app.add_plugin(TransformPlugin::default());
+        app.add_plugin(bevy::input::InputPlugin::default());
+        app.add_plugin(AssetPlugin::default());
+        app.add_plugin(ScenePlugin::default());
+        // The glTF loader stores meshes, images and materials as labeled assets, so their
+        // storages must exist even though nothing is going to render them.
+        app.add_asset::<Mesh>();
+        app.add_asset::<Image>();
+        app.add_asset::<StandardMaterial>();
+        app.add_plugin(GltfPlugin::default());
+        app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default());
+        app.add_plugin(TweeningPlugin);
+        add_game_logic(
+            app,
+            GltfSpawnerPlugin {
+                spawn_meshes: false,
+            },
+        );
+    }
+
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+fn add_game_logic(app: &mut App, gltf_spawner_plugin: GltfSpawnerPlugin) {
+    app.add_state(AppState::Menu(MenuState::Main));
+    app.add_plugin(LoadingPlugin);
+    app.add_plugin(gltf_spawner_plugin);
+    app.add_plugin(GameSystemsPlugin);
+    app.add_plugin(ReplayPlugin);
+}
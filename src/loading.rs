@@ -1,14 +1,87 @@
+use std::marker::PhantomData;
+
+use bevy::asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
 use bevy::gltf::Gltf;
 use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
 use bevy_asset_loader::{AssetCollection, AssetCollectionApp};
-//use bevy_kira_audio::AudioSource;
+use bevy_kira_audio::AudioSource;
+use serde::de::DeserializeOwned;
+
+use crate::level::{Campaign, Level};
 
 pub struct LoadingPlugin;
 
 impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
+        app.add_asset::<Level>();
+        app.init_asset_loader::<RonAssetLoader<Level>>();
+        app.add_asset::<Campaign>();
+        app.init_asset_loader::<RonAssetLoader<Campaign>>();
         app.init_collection::<ModelAssets>();
+        app.init_collection::<LevelAssets>();
+    }
+}
+
+/// An asset that is deserialized from a RON file by [`RonAssetLoader`].
+pub trait RonAsset: DeserializeOwned + TypeUuid + Send + Sync + 'static {
+    /// The file name endings of the asset files, without the leading dot - e.g. `level.ron`.
+    const EXTENSIONS: &'static [&'static str];
+
+    /// Rejects files that deserialize fine but that the game cannot use.
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Loads the other files the asset refers to. Called after [`RonAsset::validate`].
+    fn load_referenced_assets<'a>(
+        &'a mut self,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+pub fn parse_ron_asset<T: RonAsset>(bytes: &[u8]) -> Result<T, anyhow::Error> {
+    let asset: T = ron::de::from_bytes(bytes)?;
+    asset.validate()?;
+    Ok(asset)
+}
+
+/// Register one for each [`RonAsset`] type, with `app.init_asset_loader::<RonAssetLoader<T>>()`.
+pub struct RonAssetLoader<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for RonAssetLoader<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: RonAsset> AssetLoader for RonAssetLoader<T> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mut asset: T = parse_ron_asset(bytes)?;
+            asset.load_referenced_assets(load_context).await?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        T::EXTENSIONS
+    }
+}
+
+pub struct PresentationLoadingPlugin;
+
+impl Plugin for PresentationLoadingPlugin {
+    fn build(&self, app: &mut App) {
         app.init_collection::<FontAssets>();
+        app.init_collection::<AudioAssets>();
     }
 }
 
@@ -24,8 +97,36 @@ pub struct ModelAssets {
     pub woodchip: Handle<Gltf>,
 }
 
+#[derive(AssetCollection)]
+pub struct LevelAssets {
+    #[asset(path = "levels/endless.level.ron")]
+    pub endless: Handle<Level>,
+    #[asset(path = "levels/campaign.campaign.ron")]
+    pub campaign: Handle<Campaign>,
+}
+
 #[derive(AssetCollection)]
 pub struct FontAssets {
     #[asset(path = "fonts/FiraSans-Bold.ttf")]
     pub fira_sans: Handle<Font>,
 }
+
+#[derive(AssetCollection)]
+pub struct AudioAssets {
+    #[asset(path = "audio/music.ogg")]
+    pub music: Handle<AudioSource>,
+    #[asset(path = "audio/jump.wav")]
+    pub jump: Handle<AudioSource>,
+    #[asset(path = "audio/chip.wav")]
+    pub chip: Handle<AudioSource>,
+    #[asset(path = "audio/jam.wav")]
+    pub jam: Handle<AudioSource>,
+    #[asset(path = "audio/unjam.wav")]
+    pub unjam: Handle<AudioSource>,
+    #[asset(path = "audio/death.wav")]
+    pub death: Handle<AudioSource>,
+    #[asset(path = "audio/saw.wav")]
+    pub saw: Handle<AudioSource>,
+    #[asset(path = "audio/stall.wav")]
+    pub stall: Handle<AudioSource>,
+}
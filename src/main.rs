@@ -9,22 +9,56 @@ use bevy_egui_kbgp::{KbgpNavBindings, KbgpPlugin, KbgpSettings};
 use bevy_hanabi::HanabiPlugin;
 use bevy_rapier2d::physics::{NoUserData, RapierPhysicsPlugin};
 use bevy_tweening::TweeningPlugin;
+use jamming_chipper::global_types::{GameplayClock, SeedOverride};
+use jamming_chipper::replay::ReplaySettings;
+use jamming_chipper::settings::Settings;
 use jamming_chipper::GamePlugin;
 
 fn main() {
     let mut app = App::new();
-    app.insert_resource(Msaa { samples: 4 });
+    let settings = Settings::load();
+    app.insert_resource(Msaa {
+        samples: settings.msaa_samples,
+    });
     app.insert_resource(ClearColor(Color::rgb(0.529, 0.808, 0.922)));
     app.insert_resource(WindowDescriptor {
-        width: 800.,
-        height: 600.,
+        width: settings.window_size.0,
+        height: settings.window_size.1,
+        vsync: settings.vsync,
+        mode: settings.window_mode(),
         title: "Jamming Chipper".to_string(),
         ..Default::default()
     });
+    app.insert_resource(settings.clone());
     app.add_plugins(DefaultPlugins);
     app.add_plugin(GamePlugin);
+    app.insert_resource(SeedOverride(parse_arg("seed").map(|seed| {
+        seed.parse()
+            .unwrap_or_else(|err| panic!("Bad seed {:?}: {}", seed, err))
+    })));
+    if let Some(rate) = parse_arg("tick-rate") {
+        // The comparisons also reject NaN
+        let steps_per_second = match rate.parse::<f64>() {
+            Ok(steps_per_second) if 0.0 < steps_per_second && steps_per_second <= 1000.0 => {
+                steps_per_second
+            }
+            _ => {
+                eprintln!(
+                    "Bad tick rate {:?}: must be a number of steps per second, above 0 and up to 1000",
+                    rate
+                );
+                std::process::exit(1);
+            }
+        };
+        app.insert_resource(GameplayClock::from_rate(steps_per_second));
+    }
+    app.insert_resource(ReplaySettings {
+        record_to: parse_arg("record").map(Into::into),
+        play_from: parse_arg("replay").map(Into::into),
+    });
     app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default());
     app.add_plugin(TweeningPlugin);
+    app.add_plugin(bevy_kira_audio::AudioPlugin);
     app.insert_resource({
         let mut options = WgpuOptions::default();
         options
@@ -34,7 +68,9 @@ fn main() {
     });
     app.add_plugin(HanabiPlugin);
     app.add_plugin(bevy_egui_kbgp::bevy_egui::EguiPlugin);
-    app.insert_resource(bevy_egui_kbgp::bevy_egui::EguiSettings { scale_factor: 2.0 });
+    app.insert_resource(bevy_egui_kbgp::bevy_egui::EguiSettings {
+        scale_factor: settings.ui_scale,
+    });
     app.add_plugin(KbgpPlugin);
     app.insert_resource(KbgpSettings {
         allow_keyboard: true,
@@ -50,3 +86,19 @@ fn main() {
     });
     app.run();
 }
+
+fn parse_arg(name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        } else if let Some(value) = arg
+            .strip_prefix(&flag)
+            .and_then(|arg| arg.strip_prefix('='))
+        {
+            return Some(value.to_owned());
+        }
+    }
+    None
+}
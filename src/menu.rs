@@ -2,50 +2,49 @@ use bevy::prelude::*;
 use bevy_egui_kbgp::bevy_egui::EguiContext;
 use bevy_egui_kbgp::egui;
 use bevy_egui_kbgp::prelude::*;
-use ezinput::prelude::*;
 
-use crate::global_types::InputBinding;
-use crate::global_types::{AppState, MenuState};
+use crate::controls::{rebind, ControlAction, ControlBindings};
+use crate::global_types::{
+    format_time, AppState, GameMode, GameRng, MenuState, PlayerIndex, ScoreFormula, ScoreStatus,
+    VersusWinner,
+};
+use crate::high_scores::{HighScoreEntry, HighScores, PendingHighScore, PreviousPersonalBest};
+use crate::level::{Campaign, CampaignLevel, CampaignProgress, LevelSelection};
+use crate::loading::LevelAssets;
+use crate::settings::{Settings, MSAA_SAMPLES, UI_SCALES, WINDOW_SIZES};
+use crate::utils::some_or;
 
 pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<UnpauseEvent>();
-        app.add_system(pause_unpause_game);
         app.add_system_set(
             SystemSet::on_update(AppState::Menu(MenuState::Main)).with_system(main_menu),
         );
+        app.add_system_set(
+            SystemSet::on_update(AppState::Menu(MenuState::LevelSelect))
+                .with_system(level_select_menu),
+        );
         app.add_system_set(
             SystemSet::on_update(AppState::Menu(MenuState::Pause)).with_system(pause_menu),
         );
         app.add_system_set(
             SystemSet::on_update(AppState::Menu(MenuState::GameOver)).with_system(game_over_menu),
         );
-    }
-}
-
-struct UnpauseEvent;
-
-fn pause_unpause_game(
-    input_views: Query<&InputView<InputBinding>>,
-    mut state: ResMut<State<AppState>>,
-    mut unpause_writer: EventWriter<UnpauseEvent>,
-) {
-    if input_views
-        .iter()
-        .any(|view| view.key(&InputBinding::Pause).just_pressed())
-    {
-        match state.current() {
-            AppState::Menu(_) => {
-                unpause_writer.send(UnpauseEvent);
-            }
-            AppState::ClearLevelAndThenLoad => {}
-            AppState::LoadLevel => {}
-            AppState::Game => {
-                state.set(AppState::Menu(MenuState::Pause)).unwrap();
-            }
-        }
+        app.add_system_set(
+            SystemSet::on_update(AppState::Menu(MenuState::LevelComplete))
+                .with_system(level_complete_menu),
+        );
+        app.add_system_set(
+            SystemSet::on_update(AppState::Menu(MenuState::HighScores))
+                .with_system(high_scores_menu),
+        );
+        app.add_system_set(
+            SystemSet::on_update(AppState::Menu(MenuState::Settings)).with_system(settings_menu),
+        );
+        app.add_system_set(
+            SystemSet::on_update(AppState::Menu(MenuState::Controls)).with_system(controls_menu),
+        );
     }
 }
 
@@ -63,6 +62,8 @@ fn menu_layout(egui_context: &egui::Context, dlg: impl FnOnce(&mut egui::Ui)) {
 fn main_menu(
     mut egui_context: ResMut<EguiContext>,
     mut state: ResMut<State<AppState>>,
+    mut game_mode: ResMut<GameMode>,
+    mut level_selection: ResMut<LevelSelection>,
     #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<bevy::app::AppExit>,
 ) {
     menu_layout(egui_context.ctx_mut(), |ui| {
@@ -72,6 +73,30 @@ fn main_menu(
             .kbgp_initial_focus()
             .clicked()
         {
+            *game_mode = GameMode::SinglePlayer;
+            *level_selection = LevelSelection::Endless;
+            state.set(AppState::ClearLevelAndThenLoad).unwrap();
+        }
+        if ui.button("Campaign").kbgp_navigation().clicked() {
+            state.set(AppState::Menu(MenuState::LevelSelect)).unwrap();
+            ui.kbgp_clear_input();
+        }
+        if ui.button("High Scores").kbgp_navigation().clicked() {
+            state.set(AppState::Menu(MenuState::HighScores)).unwrap();
+            ui.kbgp_clear_input();
+        }
+        if ui.button("Settings").kbgp_navigation().clicked() {
+            state.push(AppState::Menu(MenuState::Settings)).unwrap();
+            ui.kbgp_clear_input();
+        }
+        if ui.button("Co-op").kbgp_navigation().clicked() {
+            *game_mode = GameMode::CoOp;
+            *level_selection = LevelSelection::Endless;
+            state.set(AppState::ClearLevelAndThenLoad).unwrap();
+        }
+        if ui.button("Versus").kbgp_navigation().clicked() {
+            *game_mode = GameMode::Versus;
+            *level_selection = LevelSelection::Endless;
             state.set(AppState::ClearLevelAndThenLoad).unwrap();
         }
         #[cfg(not(target_arch = "wasm32"))]
@@ -94,7 +119,10 @@ fn main_menu(
             r#"
             Use Left/Right keys, A/D, or gamepad to move left and right.
             Use Up key, W, or gamepad south button to jump.
+            On touch screens, touch the left half to move and the right half to jump.
             Jump on the wood chips to clear them.
+            In co-op and versus, every player presses jump to join.
+            In versus, bump the others into the chippers - last one standing wins.
             "#,
         );
         ui.label(
@@ -110,22 +138,77 @@ fn main_menu(
     });
 }
 
+fn level_select_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State<AppState>>,
+    mut game_mode: ResMut<GameMode>,
+    mut level_selection: ResMut<LevelSelection>,
+    level_assets: Res<LevelAssets>,
+    campaigns: Res<Assets<Campaign>>,
+    campaign_progress: Res<CampaignProgress>,
+) {
+    menu_layout(egui_context.ctx_mut(), |ui| {
+        if let Some(campaign) = campaigns.get(&level_assets.campaign) {
+            for (index, campaign_level) in campaign.levels.iter().enumerate() {
+                let text = format!("{}. {}", index + 1, campaign_level.name);
+                if index < campaign_progress.unlocked {
+                    let mut response = ui.button(text).kbgp_navigation();
+                    if index + 1 == campaign_progress.unlocked {
+                        response = response.kbgp_initial_focus();
+                    }
+                    if response.clicked() {
+                        *game_mode = GameMode::SinglePlayer;
+                        *level_selection = LevelSelection::Campaign(index);
+                        state.set(AppState::ClearLevelAndThenLoad).unwrap();
+                    }
+                    ui.colored_label(egui::Color32::DARK_GRAY, campaign_level.goal.describe());
+                } else {
+                    ui.colored_label(egui::Color32::DARK_GRAY, format!("{} (locked)", text));
+                }
+                ui.add_space(5.0);
+            }
+        } else {
+            ui.label("Loading...");
+        }
+        if ui.button("Back").kbgp_navigation().clicked() {
+            state.set(AppState::Menu(MenuState::Main)).unwrap();
+            ui.kbgp_clear_input();
+        }
+    });
+}
+
 fn pause_menu(
     mut egui_context: ResMut<EguiContext>,
     mut state: ResMut<State<AppState>>,
-    mut unpause_reader: EventReader<UnpauseEvent>,
+    game_rng: Res<GameRng>,
+    level_selection: Res<LevelSelection>,
+    level_assets: Res<LevelAssets>,
+    campaigns: Res<Assets<Campaign>>,
     #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<bevy::app::AppExit>,
 ) {
     menu_layout(egui_context.ctx_mut(), |ui| {
+        if let Some((_, campaign_level)) =
+            selected_campaign_level(&level_selection, &level_assets, &campaigns)
+        {
+            ui.label(format!(
+                "{}: {}",
+                campaign_level.name,
+                campaign_level.goal.describe()
+            ));
+            ui.add_space(5.0);
+        }
         if ui
             .button("Resume")
             .kbgp_navigation()
             .kbgp_initial_focus()
             .clicked()
-            || unpause_reader.iter().any(|_| true)
         {
             state.set(AppState::Game).unwrap();
         }
+        if ui.button("Settings").kbgp_navigation().clicked() {
+            state.push(AppState::Menu(MenuState::Settings)).unwrap();
+            ui.kbgp_clear_input();
+        }
         if ui.button("Main Menu").kbgp_navigation().clicked() {
             state.set(AppState::Menu(MenuState::Main)).unwrap();
             ui.kbgp_clear_input();
@@ -134,23 +217,142 @@ fn pause_menu(
         if ui.button("Exit").kbgp_navigation().clicked() {
             exit.send(bevy::app::AppExit);
         }
+        seed_label(ui, &game_rng);
     });
 }
 
 fn game_over_menu(
     mut egui_context: ResMut<EguiContext>,
     mut state: ResMut<State<AppState>>,
+    game_rng: Res<GameRng>,
+    game_mode: Res<GameMode>,
+    versus_winner: Res<VersusWinner>,
+    players_query: Query<(&PlayerIndex, &ScoreStatus)>,
+    score_status: Res<ScoreStatus>,
+    score_formula: Res<ScoreFormula>,
+    previous_personal_best: Option<Res<PreviousPersonalBest>>,
+    pending_high_score: Option<ResMut<PendingHighScore>>,
+    mut high_scores: ResMut<HighScores>,
     #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<bevy::app::AppExit>,
 ) {
     menu_layout(egui_context.ctx_mut(), |ui| {
-        if ui
-            .button("Retry")
-            .kbgp_navigation()
-            .kbgp_initial_focus()
-            .clicked()
-        {
+        if *game_mode == GameMode::Versus {
+            versus_results(ui, &versus_winner, &score_formula, players_query.iter());
+        } else {
+            run_results(
+                ui,
+                &score_status,
+                &score_formula,
+                previous_personal_best
+                    .as_ref()
+                    .and_then(|previous_personal_best| previous_personal_best.0.as_ref()),
+            );
+        }
+        let is_entering_name = if let Some(mut pending_high_score) = pending_high_score {
+            high_score_name_entry(ui, &mut pending_high_score, &mut high_scores)
+        } else {
+            false
+        };
+        let mut retry_button = ui.button("Retry").kbgp_navigation();
+        if !is_entering_name {
+            retry_button = retry_button.kbgp_initial_focus();
+        }
+        if retry_button.clicked() {
+            state.set(AppState::ClearLevelAndThenLoad).unwrap();
+        }
+        if ui.button("Main Menu").kbgp_navigation().clicked() {
+            state.set(AppState::Menu(MenuState::Main)).unwrap();
+            ui.kbgp_clear_input();
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if ui.button("Exit").kbgp_navigation().clicked() {
+            exit.send(bevy::app::AppExit);
+        }
+        seed_label(ui, &game_rng);
+    });
+}
+
+fn seed_label(ui: &mut egui::Ui, game_rng: &GameRng) {
+    ui.add_space(5.0);
+    ui.colored_label(
+        egui::Color32::DARK_GRAY,
+        format!("Seed: {}", game_rng.seed()),
+    );
+}
+
+fn versus_results<'a>(
+    ui: &mut egui::Ui,
+    versus_winner: &VersusWinner,
+    score_formula: &ScoreFormula,
+    players: impl Iterator<Item = (&'a PlayerIndex, &'a ScoreStatus)>,
+) {
+    let headline = if let Some(PlayerIndex(winner_index)) = versus_winner.0 {
+        format!("Player {} Wins!", winner_index + 1)
+    } else {
+        "Draw!".to_owned()
+    };
+    ui.label(
+        egui::RichText::new(headline)
+            .strong()
+            .color(egui::Color32::YELLOW)
+            .text_style(egui::TextStyle::Heading),
+    );
+    let mut players: Vec<_> = players.collect();
+    players.sort_by_key(|(player_index, _)| **player_index);
+    for (PlayerIndex(player_index), score_status) in players {
+        ui.label(format!(
+            "Player {}: {} points, survived {}, cleared {} wood chips",
+            player_index + 1,
+            score_status.score(score_formula),
+            score_status.format_time(),
+            score_status.woodchips_cleared,
+        ));
+    }
+    ui.add_space(10.0);
+}
+
+fn level_complete_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State<AppState>>,
+    mut level_selection: ResMut<LevelSelection>,
+    level_assets: Res<LevelAssets>,
+    campaigns: Res<Assets<Campaign>>,
+    #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<bevy::app::AppExit>,
+) {
+    let (level_index, campaign_level) =
+        some_or!(selected_campaign_level(&level_selection, &level_assets, &campaigns); return);
+    let has_next_level = campaigns
+        .get(&level_assets.campaign)
+        .map_or(false, |campaign| level_index + 1 < campaign.levels.len());
+    let mut next_level_selection = None;
+    menu_layout(egui_context.ctx_mut(), |ui| {
+        ui.label(
+            egui::RichText::new(format!("{} Complete!", campaign_level.name))
+                .strong()
+                .color(egui::Color32::YELLOW)
+                .text_style(egui::TextStyle::Heading),
+        );
+        ui.add_space(10.0);
+        if has_next_level {
+            if ui
+                .button("Next Level")
+                .kbgp_navigation()
+                .kbgp_initial_focus()
+                .clicked()
+            {
+                next_level_selection = Some(LevelSelection::Campaign(level_index + 1));
+                state.set(AppState::ClearLevelAndThenLoad).unwrap();
+            }
+        } else {
+            ui.label("You have completed the campaign!");
+        }
+        if ui.button("Replay Level").kbgp_navigation().clicked() {
             state.set(AppState::ClearLevelAndThenLoad).unwrap();
         }
+        if ui.button("Level Select").kbgp_navigation().clicked() {
+            state.set(AppState::Menu(MenuState::LevelSelect)).unwrap();
+            ui.kbgp_clear_input();
+        }
         if ui.button("Main Menu").kbgp_navigation().clicked() {
             state.set(AppState::Menu(MenuState::Main)).unwrap();
             ui.kbgp_clear_input();
@@ -160,4 +362,473 @@ fn game_over_menu(
             exit.send(bevy::app::AppExit);
         }
     });
+    if let Some(next_level_selection) = next_level_selection {
+        *level_selection = next_level_selection;
+    }
+}
+
+fn selected_campaign_level<'a>(
+    level_selection: &LevelSelection,
+    level_assets: &LevelAssets,
+    campaigns: &'a Assets<Campaign>,
+) -> Option<(usize, &'a CampaignLevel)> {
+    if let LevelSelection::Campaign(level_index) = level_selection {
+        let campaign = campaigns.get(&level_assets.campaign)?;
+        Some((*level_index, campaign.levels.get(*level_index)?))
+    } else {
+        None
+    }
+}
+
+fn run_results(
+    ui: &mut egui::Ui,
+    score_status: &ScoreStatus,
+    score_formula: &ScoreFormula,
+    personal_best: Option<&HighScoreEntry>,
+) {
+    ui.label(
+        egui::RichText::new("Game Over")
+            .strong()
+            .color(egui::Color32::RED)
+            .text_style(egui::TextStyle::Heading),
+    );
+    ui.add_space(5.0);
+    let score = score_status.score(score_formula);
+    // Each row is the stat name, its value in this run, and - when it can be compared - whether
+    // it beats the personal best and the personal best value.
+    let rows: [(&str, String, Option<(bool, String)>); 7] = [
+        (
+            "Score",
+            score.to_string(),
+            personal_best.map(|best| (best.score < score, best.score.to_string())),
+        ),
+        (
+            "Survival time",
+            score_status.format_time(),
+            personal_best.map(|best| (best.time < score_status.time, best.format_time())),
+        ),
+        (
+            "Logs chipped",
+            score_status.logs_chipped.to_string(),
+            personal_best.map(|best| {
+                (
+                    best.logs_chipped < score_status.logs_chipped,
+                    best.logs_chipped.to_string(),
+                )
+            }),
+        ),
+        (
+            "Wood chips cleared",
+            score_status.woodchips_cleared.to_string(),
+            personal_best.map(|best| {
+                (
+                    best.woodchips_cleared < score_status.woodchips_cleared,
+                    best.woodchips_cleared.to_string(),
+                )
+            }),
+        ),
+        ("Longest jam", format_time(score_status.longest_jam), None),
+        ("Jumps", score_status.jumps.to_string(), None),
+        (
+            "Cause of death",
+            score_status
+                .cause_of_death
+                .map_or("-", |cause_of_death| cause_of_death.describe())
+                .to_owned(),
+            None,
+        ),
+    ];
+    egui::Grid::new("run-results")
+        .num_columns(3)
+        .spacing([20.0, 5.0])
+        .show(ui, |ui| {
+            ui.label("");
+            ui.colored_label(egui::Color32::DARK_GRAY, "This run");
+            if personal_best.is_some() {
+                ui.colored_label(egui::Color32::DARK_GRAY, "Personal best");
+            }
+            ui.end_row();
+            for (name, value, comparison) in rows {
+                ui.label(name);
+                if let Some((is_better, best_value)) = comparison {
+                    if is_better {
+                        ui.colored_label(egui::Color32::GREEN, value);
+                    } else {
+                        ui.label(value);
+                    }
+                    ui.label(best_value);
+                } else {
+                    ui.label(value);
+                }
+                ui.end_row();
+            }
+        });
+    ui.add_space(10.0);
+}
+
+/// Returns `true` while the name is still being entered.
+fn high_score_name_entry(
+    ui: &mut egui::Ui,
+    pending_high_score: &mut PendingHighScore,
+    high_scores: &mut HighScores,
+) -> bool {
+    if let Some(position) = pending_high_score.saved_at {
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            format!("Your run is #{} in the high scores!", position + 1),
+        );
+        ui.add_space(10.0);
+        return false;
+    }
+    ui.label(
+        egui::RichText::new("New High Score!")
+            .strong()
+            .color(egui::Color32::YELLOW)
+            .text_style(egui::TextStyle::Heading),
+    );
+    ui.horizontal(|ui| {
+        ui.label("Name:");
+        ui.add(
+            egui::TextEdit::singleline(&mut pending_high_score.entry.name)
+                .char_limit(16)
+                .desired_width(150.0),
+        )
+        .kbgp_navigation()
+        .kbgp_initial_focus();
+    });
+    if ui.button("Save").kbgp_navigation().clicked() {
+        let mut entry = pending_high_score.entry.clone();
+        entry.name = entry.name.trim().to_owned();
+        if entry.name.is_empty() {
+            entry.name = "Anonymous".to_owned();
+        }
+        pending_high_score.saved_at = high_scores.insert(entry);
+    }
+    ui.add_space(10.0);
+    true
+}
+
+fn high_scores_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State<AppState>>,
+    high_scores: Res<HighScores>,
+) {
+    menu_layout(egui_context.ctx_mut(), |ui| {
+        ui.label(
+            egui::RichText::new("High Scores")
+                .strong()
+                .color(egui::Color32::YELLOW)
+                .text_style(egui::TextStyle::Heading),
+        );
+        ui.add_space(10.0);
+        if high_scores.entries.is_empty() {
+            ui.colored_label(egui::Color32::DARK_GRAY, "No high scores yet");
+        } else {
+            egui::Grid::new("high-scores")
+                .num_columns(6)
+                .spacing([20.0, 5.0])
+                .show(ui, |ui| {
+                    for header in ["", "Name", "Score", "Time", "Logs", "Chips"] {
+                        ui.colored_label(egui::Color32::DARK_GRAY, header);
+                    }
+                    ui.end_row();
+                    for (index, entry) in high_scores.entries.iter().enumerate() {
+                        ui.label(format!("{}.", index + 1));
+                        ui.label(&entry.name);
+                        ui.label(entry.score.to_string());
+                        ui.label(entry.format_time());
+                        ui.label(entry.logs_chipped.to_string());
+                        ui.label(entry.woodchips_cleared.to_string());
+                        ui.end_row();
+                    }
+                });
+        }
+        ui.add_space(10.0);
+        if ui
+            .button("Back")
+            .kbgp_navigation()
+            .kbgp_initial_focus()
+            .clicked()
+        {
+            state.set(AppState::Menu(MenuState::Main)).unwrap();
+            ui.kbgp_clear_input();
+        }
+    });
+}
+
+fn settings_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State<AppState>>,
+    mut settings: ResMut<Settings>,
+) {
+    menu_layout(egui_context.ctx_mut(), |ui| {
+        ui.label(
+            egui::RichText::new("Settings")
+                .strong()
+                .color(egui::Color32::YELLOW)
+                .text_style(egui::TextStyle::Heading),
+        );
+        ui.add_space(10.0);
+        // Edit a copy, so that the settings are only marked as changed (and re-applied) when
+        // something actually changes.
+        let mut edited = settings.clone();
+        egui::Grid::new("settings")
+            .num_columns(2)
+            .spacing([20.0, 5.0])
+            .show(ui, |ui| {
+                volume_setting(ui, "Music Volume", &mut edited.music_volume).kbgp_initial_focus();
+                volume_setting(ui, "Effects Volume", &mut edited.effects_volume);
+                cycle_setting(ui, "UI Scale", UI_SCALES, &mut edited.ui_scale, |scale| {
+                    format!("{}x", scale)
+                });
+                cycle_setting(ui, "Shadows", &[true, false], &mut edited.shadows, on_off);
+                cycle_setting(
+                    ui,
+                    "Anti-aliasing (after restart)",
+                    MSAA_SAMPLES,
+                    &mut edited.msaa_samples,
+                    |samples| {
+                        if samples <= 1 {
+                            "Off".to_owned()
+                        } else {
+                            format!("{}x", samples)
+                        }
+                    },
+                );
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    cycle_setting(
+                        ui,
+                        "Window Size",
+                        WINDOW_SIZES,
+                        &mut edited.window_size,
+                        |(width, height)| format!("{}x{}", width, height),
+                    );
+                    cycle_setting(
+                        ui,
+                        "Fullscreen",
+                        &[false, true],
+                        &mut edited.fullscreen,
+                        on_off,
+                    );
+                    cycle_setting(ui, "VSync", &[true, false], &mut edited.vsync, on_off);
+                }
+            });
+        if edited != *settings {
+            *settings = edited;
+            settings.save();
+        }
+        ui.add_space(10.0);
+        if ui.button("Controls").kbgp_navigation().clicked() {
+            state.push(AppState::Menu(MenuState::Controls)).unwrap();
+            ui.kbgp_clear_input();
+        }
+        if ui.button("Back").kbgp_navigation().clicked() {
+            // Settings are pushed on top of the menu they were opened from
+            state.pop().unwrap();
+            ui.kbgp_clear_input();
+        }
+    });
+}
+
+fn on_off(value: bool) -> String {
+    if value { "On" } else { "Off" }.to_owned()
+}
+
+/// A grid row with a button that switches to the next option when clicked.
+fn cycle_setting<T: Copy + PartialEq>(
+    ui: &mut egui::Ui,
+    label: &str,
+    options: &[T],
+    value: &mut T,
+    describe: impl Fn(T) -> String,
+) -> egui::Response {
+    ui.label(label);
+    let response = ui.button(describe(*value)).kbgp_navigation();
+    if response.clicked() {
+        let next_index = options
+            .iter()
+            .position(|option| option == value)
+            .map_or(0, |index| (index + 1) % options.len());
+        *value = options[next_index];
+    }
+    ui.end_row();
+    response
+}
+
+/// A grid row with buttons for lowering and raising the volume in steps of 10%.
+fn volume_setting(ui: &mut egui::Ui, label: &str, volume: &mut f32) -> egui::Response {
+    ui.label(label);
+    let steps = (*volume * 10.0).round();
+    let response = ui
+        .horizontal(|ui| {
+            let lower_response = ui.button("-").kbgp_navigation();
+            if lower_response.clicked() {
+                *volume = (steps - 1.0).max(0.0) / 10.0;
+            }
+            ui.label(format!("{:>3}%", steps * 10.0));
+            if ui.button("+").kbgp_navigation().clicked() {
+                *volume = (steps + 1.0).min(10.0) / 10.0;
+            }
+            lower_response
+        })
+        .inner;
+    ui.end_row();
+    response
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ControlDevice {
+    Keyboard,
+    Gamepad,
+}
+
+#[derive(Default)]
+struct ControlsMenuStatus {
+    /// The binding that will be replaced by the next key or button press.
+    rebinding: Option<(ControlAction, ControlDevice)>,
+    conflict: Option<String>,
+}
+
+fn controls_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State<AppState>>,
+    mut bindings: ResMut<ControlBindings>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut status: Local<ControlsMenuStatus>,
+) {
+    // Check for the new binding before drawing the menu, so that the press that started the
+    // rebinding is not taken as the new binding.
+    let mut binding_done = false;
+    // Escape cancels the rebinding, so it can only be bound by resetting to the defaults
+    if status.rebinding.is_some() && keyboard.just_pressed(KeyCode::Escape) {
+        status.rebinding = None;
+        binding_done = true;
+    }
+    if let Some((action, device)) = status.rebinding {
+        let pressed_key = keyboard.get_just_pressed().next().copied();
+        let pressed_button = gamepad_buttons
+            .get_just_pressed()
+            .next()
+            .map(|GamepadButton(_, button_type)| *button_type);
+        let mut edited = bindings.clone();
+        let result = match (device, pressed_key, pressed_button) {
+            (ControlDevice::Keyboard, Some(key), _) => Some(
+                rebind(&mut edited.keyboard, action, key).map_err(|other_action| {
+                    format!("{:?} is already used for {}", key, other_action.describe())
+                }),
+            ),
+            (ControlDevice::Gamepad, _, Some(button)) => Some(
+                rebind(&mut edited.gamepad, action, button).map_err(|other_action| {
+                    format!(
+                        "{:?} is already used for {}",
+                        button,
+                        other_action.describe()
+                    )
+                }),
+            ),
+            _ => None,
+        };
+        match result {
+            None => {}
+            Some(Ok(())) => {
+                *bindings = edited;
+                bindings.save();
+                status.rebinding = None;
+                status.conflict = None;
+                binding_done = true;
+            }
+            Some(Err(conflict)) => {
+                status.rebinding = None;
+                status.conflict = Some(conflict);
+                binding_done = true;
+            }
+        }
+    }
+
+    menu_layout(egui_context.ctx_mut(), |ui| {
+        // Otherwise the press that was taken as the binding would also activate the focused button
+        if binding_done {
+            ui.kbgp_clear_input();
+        }
+        ui.label(
+            egui::RichText::new("Controls")
+                .strong()
+                .color(egui::Color32::YELLOW)
+                .text_style(egui::TextStyle::Heading),
+        );
+        ui.add_space(10.0);
+        egui::Grid::new("controls")
+            .num_columns(3)
+            .spacing([20.0, 5.0])
+            .show(ui, |ui| {
+                for header in ["", "Keyboard", "Gamepad"] {
+                    ui.colored_label(egui::Color32::DARK_GRAY, header);
+                }
+                ui.end_row();
+                for (index, action) in ControlAction::ALL.iter().enumerate() {
+                    ui.label(action.describe());
+                    for device in [ControlDevice::Keyboard, ControlDevice::Gamepad] {
+                        let text = if status.rebinding == Some((*action, device)) {
+                            "Press...".to_owned()
+                        } else {
+                            let bound: Vec<String> = match device {
+                                ControlDevice::Keyboard => bindings
+                                    .keyboard
+                                    .iter()
+                                    .filter(|(bound_action, _)| bound_action == action)
+                                    .map(|(_, key)| format!("{:?}", key))
+                                    .collect(),
+                                ControlDevice::Gamepad => bindings
+                                    .gamepad
+                                    .iter()
+                                    .filter(|(bound_action, _)| bound_action == action)
+                                    .map(|(_, button)| format!("{:?}", button))
+                                    .collect(),
+                            };
+                            if bound.is_empty() {
+                                "-".to_owned()
+                            } else {
+                                bound.join(" / ")
+                            }
+                        };
+                        let mut response = ui.button(text).kbgp_navigation();
+                        if index == 0 && device == ControlDevice::Keyboard {
+                            response = response.kbgp_initial_focus();
+                        }
+                        if response.clicked() {
+                            status.rebinding = Some((*action, device));
+                            status.conflict = None;
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        ui.add_space(5.0);
+        if let Some(conflict) = &status.conflict {
+            ui.colored_label(egui::Color32::RED, conflict);
+        } else if status.rebinding.is_some() {
+            ui.colored_label(
+                egui::Color32::DARK_GRAY,
+                "Press the new key or gamepad button - Escape cancels",
+            );
+        }
+        ui.add_space(10.0);
+        if ui.button("Reset to Defaults").kbgp_navigation().clicked() {
+            *bindings = Default::default();
+            bindings.save();
+            status.rebinding = None;
+            status.conflict = None;
+        }
+        if ui.button("Back").kbgp_navigation().clicked() {
+            if status.rebinding.is_some() {
+                status.rebinding = None;
+            } else {
+                *status = Default::default();
+                state.pop().unwrap();
+            }
+            ui.kbgp_clear_input();
+        }
+    });
 }
@@ -0,0 +1,83 @@
+//! Keeps small pieces of data between sessions - as files in the platform's config directory on
+//! native, and in the browser's local storage on the web.
+
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Returns `None` if nothing was saved under that key, or if it could not be read.
+pub fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let content = match read(key) {
+        Ok(Some(content)) => content,
+        Ok(None) => return None,
+        Err(err) => {
+            error!("Unable to read {}: {}", key, err);
+            return None;
+        }
+    };
+    match ron::from_str(&content) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            error!("Unable to parse {}: {}", key, err);
+            None
+        }
+    }
+}
+
+pub fn save<T: Serialize>(key: &str, value: &T) {
+    let result = ron::ser::to_string_pretty(value, Default::default())
+        .map_err(|err| err.to_string())
+        .and_then(|content| write(key, &content));
+    if let Err(err) = result {
+        error!("Unable to save {}: {}", key, err);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn file_path(key: &str) -> Result<std::path::PathBuf, String> {
+    let project_dirs = directories::ProjectDirs::from("", "", "jamming-chipper")
+        .ok_or_else(|| "no config directory".to_owned())?;
+    Ok(project_dirs.config_dir().join(format!("{}.ron", key)))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read(key: &str) -> Result<Option<String>, String> {
+    let path = file_path(key)?;
+    match std::fs::read_to_string(&path) {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("{:?}: {}", path, err)),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write(key: &str, content: &str) -> Result<(), String> {
+    let path = file_path(key)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|err| format!("{:?}: {}", parent, err))?;
+    }
+    std::fs::write(&path, content).map_err(|err| format!("{:?}: {}", path, err))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Result<web_sys::Storage, String> {
+    web_sys::window()
+        .ok_or_else(|| "no window".to_owned())?
+        .local_storage()
+        .map_err(|err| format!("{:?}", err))?
+        .ok_or_else(|| "no local storage".to_owned())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read(key: &str) -> Result<Option<String>, String> {
+    local_storage()?
+        .get_item(&format!("jamming-chipper.{}", key))
+        .map_err(|err| format!("{:?}", err))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write(key: &str, content: &str) -> Result<(), String> {
+    local_storage()?
+        .set_item(&format!("jamming-chipper.{}", key), content)
+        .map_err(|err| format!("{:?}", err))
+}
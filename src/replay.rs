@@ -0,0 +1,289 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game_systems::gameplay_system_set;
+use crate::global_types::{
+    AppState, GameMode, GameRng, GameplayClock, GameplayStage, GameplaySystemLabel, InputSource,
+    JoinPlayerEvent, MenuState, PlayerControlledBy, PlayerIndex, PlayerInput, SeedOverride,
+};
+use crate::level::LevelSelection;
+use crate::utils::some_or;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplaySettings>();
+        app.add_startup_system(setup_replay);
+        app.add_system_set(SystemSet::on_enter(AppState::LoadLevel).with_system(rewind_replay));
+        app.add_system(play_replay_pauses);
+        app.add_system_set(
+            SystemSet::on_enter(AppState::Menu(MenuState::Pause)).with_system(record_pause_start),
+        );
+        app.add_system_set(
+            SystemSet::on_exit(AppState::Menu(MenuState::Pause)).with_system(record_pause_end),
+        );
+        app.add_system_set_to_stage(GameplayStage, {
+            gameplay_system_set()
+                .with_system(
+                    play_replay_frame
+                        .label(GameplaySystemLabel::JoinPlayers)
+                        .label(GameplaySystemLabel::OverrideInput),
+                )
+                .with_system(
+                    record_replay_frame
+                        .after(GameplaySystemLabel::JoinPlayers)
+                        .after(GameplaySystemLabel::OverrideInput),
+                )
+        });
+        app.add_system_set(
+            SystemSet::on_enter(AppState::Menu(MenuState::GameOver)).with_system(save_recording),
+        );
+        app.add_system_set(
+            SystemSet::on_enter(AppState::Menu(MenuState::LevelComplete))
+                .with_system(save_recording),
+        );
+    }
+}
+
+/// Where to record the played sessions to, and where to play a session back from.
+#[derive(Default)]
+pub struct ReplaySettings {
+    pub record_to: Option<PathBuf>,
+    pub play_from: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Replay {
+    seed: u64,
+    step: Duration,
+    game_mode: GameMode,
+    #[serde(default)]
+    level_selection: LevelSelection,
+    frames: Vec<ReplayFrame>,
+    #[serde(default)]
+    pauses: Vec<ReplayPause>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReplayFrame {
+    /// How many co-op players joined in this frame.
+    joined: usize,
+    /// Ordered by [`PlayerIndex`].
+    inputs: Vec<PlayerInput>,
+}
+
+/// The gameplay clock keeps stepping while the game is paused, so the steps at which the pause
+/// starts and ends tell both when to pause and for how long. Counted from the start of the level.
+#[derive(Serialize, Deserialize)]
+struct ReplayPause {
+    start: u64,
+    end: u64,
+}
+
+struct ReplayPlayback {
+    replay: Replay,
+    next_frame: usize,
+    next_pause: usize,
+    start_step: u64,
+}
+
+struct ReplayRecording {
+    replay: Replay,
+    start_step: u64,
+    pause_start: Option<u64>,
+}
+
+fn setup_replay(
+    mut commands: Commands,
+    settings: Res<ReplaySettings>,
+    mut seed_override: ResMut<SeedOverride>,
+    mut state: ResMut<State<AppState>>,
+    mut clock: ResMut<GameplayClock>,
+    mut game_mode: ResMut<GameMode>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut input_source: ResMut<InputSource>,
+) {
+    let play_from = if let Some(play_from) = &settings.play_from {
+        play_from
+    } else {
+        return;
+    };
+    let replay: Replay = match std::fs::read_to_string(play_from)
+        .map_err(|err| err.to_string())
+        .and_then(|content| ron::from_str(&content).map_err(|err| err.to_string()))
+    {
+        Ok(replay) => replay,
+        Err(err) => {
+            error!("Unable to load replay from {:?}: {}", play_from, err);
+            return;
+        }
+    };
+    info!(
+        "Playing {} frames from {:?}",
+        replay.frames.len(),
+        play_from
+    );
+    seed_override.0 = Some(replay.seed);
+    clock.step = replay.step;
+    // Physics runs inside the gameplay steps, so the results do not depend on how the steps are
+    // spread over the frames - and there is no need to wait for the real time to pass.
+    clock.one_step_per_frame = true;
+    *game_mode = replay.game_mode;
+    *level_selection = replay.level_selection;
+    *input_source = InputSource::Replay;
+    commands.insert_resource(ReplayPlayback {
+        replay,
+        next_frame: 0,
+        next_pause: 0,
+        start_step: 0,
+    });
+    state.set(AppState::ClearLevelAndThenLoad).unwrap();
+}
+
+fn rewind_replay(
+    mut commands: Commands,
+    settings: Res<ReplaySettings>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    game_rng: Res<GameRng>,
+    clock: Res<GameplayClock>,
+    game_mode: Res<GameMode>,
+    level_selection: Res<LevelSelection>,
+    mut input_source: ResMut<InputSource>,
+) {
+    if let Some(mut playback) = playback {
+        playback.next_frame = 0;
+        playback.next_pause = 0;
+        playback.start_step = clock.steps_taken();
+        *input_source = InputSource::Replay;
+    }
+    if settings.record_to.is_some() {
+        commands.insert_resource(ReplayRecording {
+            replay: Replay {
+                seed: game_rng.seed(),
+                step: clock.step,
+                game_mode: *game_mode,
+                level_selection: *level_selection,
+                frames: Vec::new(),
+                pauses: Vec::new(),
+            },
+            start_step: clock.steps_taken(),
+            pause_start: None,
+        });
+    }
+}
+
+fn play_replay_frame(
+    playback: Option<ResMut<ReplayPlayback>>,
+    mut input_source: ResMut<InputSource>,
+    mut players_query: Query<(&PlayerIndex, &mut PlayerInput)>,
+    mut join_writer: EventWriter<JoinPlayerEvent>,
+) {
+    let mut playback = if let Some(playback) = playback {
+        playback
+    } else {
+        return;
+    };
+    if let Some(frame) = playback.replay.frames.get(playback.next_frame) {
+        for (PlayerIndex(index), mut player_input) in players_query.iter_mut() {
+            if let Some(recorded_input) = frame.inputs.get(*index) {
+                *player_input = *recorded_input;
+            }
+        }
+        for _ in 0..frame.joined {
+            join_writer.send(JoinPlayerEvent(PlayerControlledBy::Replay));
+        }
+    } else if playback.next_frame == playback.replay.frames.len() {
+        info!("Replay finished - back to live input");
+        *input_source = InputSource::Live;
+    } else {
+        return;
+    }
+    playback.next_frame += 1;
+}
+
+fn record_replay_frame(
+    recording: Option<ResMut<ReplayRecording>>,
+    mut join_reader: EventReader<JoinPlayerEvent>,
+    players_query: Query<(&PlayerIndex, &PlayerInput)>,
+) {
+    let mut recording = if let Some(recording) = recording {
+        recording
+    } else {
+        return;
+    };
+    let mut players: Vec<_> = players_query.iter().collect();
+    players.sort_by_key(|(index, _)| **index);
+    recording.replay.frames.push(ReplayFrame {
+        joined: join_reader.iter().count(),
+        inputs: players
+            .into_iter()
+            .map(|(_, player_input)| *player_input)
+            .collect(),
+    });
+}
+
+fn play_replay_pauses(
+    playback: Option<ResMut<ReplayPlayback>>,
+    input_source: Res<InputSource>,
+    clock: Res<GameplayClock>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let mut playback = some_or!(playback; return);
+    if *input_source != InputSource::Replay {
+        return;
+    }
+    let pause = some_or!(playback.replay.pauses.get(playback.next_pause); return);
+    let step = clock.steps_taken() - playback.start_step;
+    // The pause menu buttons may have queued another transition already, so failing to set the
+    // state is not an error - it will be retried on the next frame.
+    match state.current() {
+        AppState::Game => {
+            if pause.start <= step {
+                let _ = state.set(AppState::Menu(MenuState::Pause));
+            }
+        }
+        AppState::Menu(MenuState::Pause) => {
+            if pause.end <= step && state.set(AppState::Game).is_ok() {
+                playback.next_pause += 1;
+            }
+        }
+        _ => {}
+    }
+}
+
+fn record_pause_start(recording: Option<ResMut<ReplayRecording>>, clock: Res<GameplayClock>) {
+    let mut recording = some_or!(recording; return);
+    recording.pause_start = Some(clock.steps_taken() - recording.start_step);
+}
+
+fn record_pause_end(recording: Option<ResMut<ReplayRecording>>, clock: Res<GameplayClock>) {
+    let mut recording = some_or!(recording; return);
+    let end = clock.steps_taken() - recording.start_step;
+    if let Some(start) = recording.pause_start.take() {
+        recording.replay.pauses.push(ReplayPause { start, end });
+    }
+}
+
+fn save_recording(settings: Res<ReplaySettings>, recording: Option<Res<ReplayRecording>>) {
+    let (record_to, recording) =
+        if let (Some(record_to), Some(recording)) = (&settings.record_to, recording) {
+            (record_to, recording)
+        } else {
+            return;
+        };
+    let result = ron::to_string(&recording.replay)
+        .map_err(|err| err.to_string())
+        .and_then(|content| std::fs::write(record_to, content).map_err(|err| err.to_string()));
+    match result {
+        Ok(()) => info!(
+            "Recorded {} frames to {:?}",
+            recording.replay.frames.len(),
+            record_to
+        ),
+        Err(err) => error!("Unable to save replay to {:?}: {}", record_to, err),
+    }
+}
@@ -1,16 +1,17 @@
 use bevy::prelude::*;
 
-use crate::global_types::{AppState, ScoreStatus};
+use crate::global_types::{GameMode, PlayerIndex, ScoreFormula, ScoreStatus};
 use crate::loading::FontAssets;
 
 pub struct ScoreDisplayPlugin;
 
 impl Plugin for ScoreDisplayPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(ScoreStatus::default());
         app.add_startup_system(setup_score_display);
         app.add_system(update_score_display);
-        app.add_system_set(SystemSet::on_update(AppState::Game).with_system(update_time));
+        app.add_system(add_remove_player_score_columns);
+        app.add_system(update_player_score_columns);
+        app.add_system(layout_player_score_columns);
     }
 }
 
@@ -20,7 +21,7 @@ struct ScoreDisplayText;
 fn setup_score_display(mut commands: Commands, font_assets: Res<FontAssets>) {
     let mut cmd = commands.spawn_bundle(NodeBundle {
         style: Style {
-            size: Size::new(Val::Px(0.0), Val::Px(90.0)),
+            size: Size::new(Val::Px(0.0), Val::Px(150.0)),
             position_type: PositionType::Absolute,
             justify_content: JustifyContent::FlexStart,
             position: Rect {
@@ -42,6 +43,25 @@ fn setup_score_display(mut commands: Commands, font_assets: Res<FontAssets>) {
         let mut cmd = commands.spawn_bundle(TextBundle {
             text: Text {
                 sections: vec![
+                    TextSection {
+                        value: "Score: ".to_owned(),
+                        style: text_style.clone(),
+                    },
+                    TextSection {
+                        value: String::new(),
+                        style: text_style.clone(),
+                    },
+                    TextSection {
+                        value: String::new(),
+                        style: TextStyle {
+                            color: Color::ORANGE,
+                            ..text_style.clone()
+                        },
+                    },
+                    TextSection {
+                        value: "\n".to_owned(),
+                        style: text_style.clone(),
+                    },
                     TextSection {
                         value: "Time: ".to_owned(),
                         style: text_style.clone(),
@@ -86,17 +106,193 @@ fn setup_score_display(mut commands: Commands, font_assets: Res<FontAssets>) {
     });
 }
 
-fn update_time(time: Res<Time>, mut score_status: ResMut<ScoreStatus>) {
-    score_status.time += time.delta();
-}
-
 fn update_score_display(
     mut query: Query<&mut Text, With<ScoreDisplayText>>,
     score_status: Res<ScoreStatus>,
+    score_formula: Res<ScoreFormula>,
 ) {
     for mut score_text in query.iter_mut() {
-        score_text.sections[1].value = score_status.format_time();
-        score_text.sections[4].value = score_status.logs_chipped.to_string();
-        score_text.sections[7].value = score_status.woodchips_cleared.to_string();
+        score_text.sections[1].value = score_status.score(&score_formula).to_string();
+        score_text.sections[2].value = format_combo(&score_status, &score_formula);
+        score_text.sections[5].value = score_status.format_time();
+        score_text.sections[8].value = score_status.logs_chipped.to_string();
+        score_text.sections[11].value = score_status.woodchips_cleared.to_string();
+    }
+}
+
+fn format_combo(score_status: &ScoreStatus, score_formula: &ScoreFormula) -> String {
+    if score_status.combo == 0 {
+        String::new()
+    } else {
+        format!("  x{:.1}", score_status.combo_multiplier(score_formula))
+    }
+}
+
+/// A versus score column, showing the score of the player entity.
+#[derive(Component)]
+struct PlayerScoreColumn(Entity);
+
+const SCORE_COLUMN_WIDTH: f32 = 200.0;
+const SCORE_COLUMN_HEIGHT: f32 = 100.0;
+
+/// The columns wrap to more rows when they don't fit in the window's width.
+fn score_column_position(player_index: usize, window_width: f32) -> Rect<Val> {
+    let columns_per_row = (((window_width - 10.0) / SCORE_COLUMN_WIDTH) as usize).max(1);
+    Rect {
+        left: Val::Px(10.0 + SCORE_COLUMN_WIDTH * (player_index % columns_per_row) as f32),
+        top: Val::Px(170.0 + SCORE_COLUMN_HEIGHT * (player_index / columns_per_row) as f32),
+        ..Default::default()
+    }
+}
+
+fn primary_window_width(windows: &Windows) -> f32 {
+    windows
+        .get_primary()
+        .map_or(f32::INFINITY, |window| window.width())
+}
+
+fn add_remove_player_score_columns(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    game_mode: Res<GameMode>,
+    windows: Res<Windows>,
+    players_query: Query<(Entity, &PlayerIndex), With<ScoreStatus>>,
+    columns_query: Query<(Entity, &PlayerScoreColumn)>,
+) {
+    for (column_entity, PlayerScoreColumn(player_entity)) in columns_query.iter() {
+        if *game_mode != GameMode::Versus || players_query.get(*player_entity).is_err() {
+            commands.entity(column_entity).despawn_recursive();
+        }
+    }
+    if *game_mode != GameMode::Versus {
+        return;
+    }
+    for (player_entity, PlayerIndex(player_index)) in players_query.iter() {
+        if columns_query
+            .iter()
+            .any(|(_, PlayerScoreColumn(entity))| *entity == player_entity)
+        {
+            continue;
+        }
+        let mut cmd = commands.spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(0.0), Val::Px(SCORE_COLUMN_HEIGHT)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::FlexStart,
+                position: score_column_position(*player_index, primary_window_width(&windows)),
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        });
+        cmd.insert(PlayerScoreColumn(player_entity));
+        let text_style = TextStyle {
+            font: font_assets.fira_sans.clone(),
+            font_size: 20.0,
+            color: Color::WHITE,
+        };
+        cmd.with_children(|commands| {
+            commands.spawn_bundle(TextBundle {
+                text: Text {
+                    sections: vec![
+                        TextSection {
+                            value: format!("Player {}\n", player_index + 1),
+                            style: TextStyle {
+                                color: Color::YELLOW,
+                                ..text_style.clone()
+                            },
+                        },
+                        TextSection {
+                            value: "Score: ".to_owned(),
+                            style: text_style.clone(),
+                        },
+                        TextSection {
+                            value: String::new(),
+                            style: text_style.clone(),
+                        },
+                        TextSection {
+                            value: String::new(),
+                            style: TextStyle {
+                                color: Color::ORANGE,
+                                ..text_style.clone()
+                            },
+                        },
+                        TextSection {
+                            value: "\n".to_owned(),
+                            style: text_style.clone(),
+                        },
+                        TextSection {
+                            value: "Time: ".to_owned(),
+                            style: text_style.clone(),
+                        },
+                        TextSection {
+                            value: String::new(),
+                            style: text_style.clone(),
+                        },
+                        TextSection {
+                            value: "\n".to_owned(),
+                            style: text_style.clone(),
+                        },
+                        TextSection {
+                            value: "Chips: ".to_owned(),
+                            style: text_style.clone(),
+                        },
+                        TextSection {
+                            value: String::new(),
+                            style: text_style.clone(),
+                        },
+                    ],
+                    alignment: TextAlignment {
+                        vertical: VerticalAlign::Bottom,
+                        horizontal: HorizontalAlign::Left,
+                    },
+                },
+                ..Default::default()
+            });
+        });
+    }
+}
+
+fn update_player_score_columns(
+    columns_query: Query<(&PlayerScoreColumn, &Children)>,
+    mut texts_query: Query<&mut Text>,
+    players_query: Query<&ScoreStatus>,
+    score_formula: Res<ScoreFormula>,
+) {
+    for (PlayerScoreColumn(player_entity), children) in columns_query.iter() {
+        let score_status = if let Ok(score_status) = players_query.get(*player_entity) {
+            score_status
+        } else {
+            continue;
+        };
+        for child in children.iter() {
+            if let Ok(mut score_text) = texts_query.get_mut(*child) {
+                score_text.sections[2].value = score_status.score(&score_formula).to_string();
+                score_text.sections[3].value = format_combo(score_status, &score_formula);
+                score_text.sections[6].value = score_status.format_time();
+                score_text.sections[9].value = score_status.woodchips_cleared.to_string();
+            }
+        }
+    }
+}
+
+fn layout_player_score_columns(
+    windows: Res<Windows>,
+    players_query: Query<&PlayerIndex>,
+    mut columns_query: Query<(&PlayerScoreColumn, &mut Style)>,
+) {
+    let window_width = primary_window_width(&windows);
+    for (PlayerScoreColumn(player_entity), mut style) in columns_query.iter_mut() {
+        let player_index = if let Ok(PlayerIndex(player_index)) = players_query.get(*player_entity)
+        {
+            *player_index
+        } else {
+            continue;
+        };
+        let position = score_column_position(player_index, window_width);
+        // Only touch the style when it changes, so that the UI is not laid out again every frame
+        if style.position != position {
+            style.position = position;
+        }
     }
 }
@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+use bevy::window::WindowMode;
+use bevy_egui_kbgp::bevy_egui::EguiSettings;
+use serde::{Deserialize, Serialize};
+
+use crate::persistence;
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        // The binary inserts the settings before the engine plugins, so that the window and the
+        // renderer start with them.
+        if !app.world.contains_resource::<Settings>() {
+            app.insert_resource(Settings::load());
+        }
+        app.add_system(apply_settings);
+    }
+}
+
+const STORAGE_KEY: &str = "settings";
+
+pub const WINDOW_SIZES: &[(f32, f32)] = &[
+    (800.0, 600.0),
+    (1024.0, 768.0),
+    (1280.0, 720.0),
+    (1600.0, 900.0),
+    (1920.0, 1080.0),
+];
+/// Bevy only supports these sample counts.
+pub const MSAA_SAMPLES: &[u32] = &[1, 4];
+pub const UI_SCALES: &[f64] = &[1.0, 1.5, 2.0, 2.5, 3.0];
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window_size: (f32, f32),
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// Only applied on startup.
+    pub msaa_samples: u32,
+    pub shadows: bool,
+    pub ui_scale: f64,
+    /// Between 0.0 (muted) and 1.0.
+    pub music_volume: f32,
+    /// Between 0.0 (muted) and 1.0.
+    pub effects_volume: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window_size: (800.0, 600.0),
+            fullscreen: false,
+            vsync: true,
+            msaa_samples: 4,
+            shadows: true,
+            ui_scale: 2.0,
+            music_volume: 0.5,
+            effects_volume: 1.0,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        persistence::load(STORAGE_KEY).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        persistence::save(STORAGE_KEY, self);
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        if self.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        }
+    }
+}
+
+fn apply_settings(
+    settings: Res<Settings>,
+    mut windows: ResMut<Windows>,
+    mut egui_settings: ResMut<EguiSettings>,
+    mut lights_query: Query<&mut DirectionalLight>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        // On the web the canvas size is controlled by the page
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let (width, height) = settings.window_size;
+            if window.requested_width() != width || window.requested_height() != height {
+                window.set_resolution(width, height);
+            }
+            if window.mode() != settings.window_mode() {
+                window.set_mode(settings.window_mode());
+            }
+        }
+        if window.vsync() != settings.vsync {
+            window.set_vsync(settings.vsync);
+        }
+    }
+    if egui_settings.scale_factor != settings.ui_scale {
+        egui_settings.scale_factor = settings.ui_scale;
+    }
+    for mut light in lights_query.iter_mut() {
+        light.shadows_enabled = settings.shadows;
+    }
+}
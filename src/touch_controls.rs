@@ -0,0 +1,184 @@
+//! On-screen controls for touch screens. They stay hidden until the first touch, so that they do
+//! not get in the way of keyboard and gamepad players.
+
+use bevy::input::mouse::MouseButtonInput;
+use bevy::input::touch::{TouchInput, TouchPhase};
+use bevy::input::ElementState;
+use bevy::prelude::*;
+use bevy::window::CursorMoved;
+use bevy_egui_kbgp::bevy_egui::EguiContext;
+use bevy_egui_kbgp::egui;
+
+use crate::global_types::{AppState, TouchControlsInput};
+use crate::utils::some_or;
+
+pub struct TouchControlsPlugin;
+
+impl Plugin for TouchControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TouchControls>();
+        // Before egui processes the mouse events
+        app.add_system_to_stage(CoreStage::First, tap_menus);
+        app.add_system(update_touch_controls);
+        app.add_system_set(SystemSet::on_update(AppState::Game).with_system(show_touch_controls));
+    }
+}
+
+#[derive(Default)]
+struct TouchControls {
+    detected: bool,
+    /// The touch that is used as a mouse pointer for the menus.
+    menu_touch: Option<u64>,
+    pressed_zones: Vec<TouchZone>,
+}
+
+/// The left half of the screen is for moving, and the right half is for jumping.
+#[derive(Clone, Copy, PartialEq)]
+enum TouchZone {
+    Left,
+    Right,
+    Jump,
+}
+
+impl TouchZone {
+    const ALL: [TouchZone; 3] = [TouchZone::Left, TouchZone::Right, TouchZone::Jump];
+
+    fn at(x_fraction: f32) -> Self {
+        if x_fraction < 0.25 {
+            TouchZone::Left
+        } else if x_fraction < 0.5 {
+            TouchZone::Right
+        } else {
+            TouchZone::Jump
+        }
+    }
+
+    fn x_fraction_range(&self) -> (f32, f32) {
+        match self {
+            TouchZone::Left => (0.0, 0.25),
+            TouchZone::Right => (0.25, 0.5),
+            TouchZone::Jump => (0.5, 1.0),
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            TouchZone::Left => "<",
+            TouchZone::Right => ">",
+            TouchZone::Jump => "JUMP",
+        }
+    }
+}
+
+/// The egui version we use does not handle touch events, so the first touch is turned into mouse
+/// events.
+fn tap_menus(
+    mut touch_reader: EventReader<TouchInput>,
+    windows: Res<Windows>,
+    mut touch_controls: ResMut<TouchControls>,
+    mut cursor_writer: EventWriter<CursorMoved>,
+    mut mouse_button_writer: EventWriter<MouseButtonInput>,
+) {
+    let window = some_or!(windows.get_primary(); return);
+    for touch in touch_reader.iter() {
+        touch_controls.detected = true;
+        if touch.phase == TouchPhase::Started && touch_controls.menu_touch.is_none() {
+            touch_controls.menu_touch = Some(touch.id);
+        }
+        if touch_controls.menu_touch != Some(touch.id) {
+            continue;
+        }
+        // Touch positions are measured from the top of the window, and cursor positions from the
+        // bottom.
+        cursor_writer.send(CursorMoved {
+            id: window.id(),
+            position: Vec2::new(touch.position.x, window.height() - touch.position.y),
+        });
+        match touch.phase {
+            TouchPhase::Started => {
+                mouse_button_writer.send(MouseButtonInput {
+                    button: MouseButton::Left,
+                    state: ElementState::Pressed,
+                });
+            }
+            TouchPhase::Moved => {}
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                mouse_button_writer.send(MouseButtonInput {
+                    button: MouseButton::Left,
+                    state: ElementState::Released,
+                });
+                touch_controls.menu_touch = None;
+            }
+        }
+    }
+}
+
+fn update_touch_controls(
+    touches: Res<Touches>,
+    windows: Res<Windows>,
+    mut touch_controls: ResMut<TouchControls>,
+    mut touch_controls_input: ResMut<TouchControlsInput>,
+) {
+    let window = some_or!(windows.get_primary(); return);
+    let pressed_zones: Vec<TouchZone> = touches
+        .iter()
+        .map(|touch| TouchZone::at(touch.position().x / window.width()))
+        .collect();
+    let is_pressed = |zone| pressed_zones.contains(&zone);
+    let input = TouchControlsInput {
+        move_horizontal: match (is_pressed(TouchZone::Left), is_pressed(TouchZone::Right)) {
+            (true, false) => -1.0,
+            (false, true) => 1.0,
+            _ => 0.0,
+        },
+        jump: is_pressed(TouchZone::Jump),
+    };
+    if *touch_controls_input != input {
+        *touch_controls_input = input;
+    }
+    touch_controls.pressed_zones = pressed_zones;
+}
+
+fn show_touch_controls(mut egui_context: ResMut<EguiContext>, touch_controls: Res<TouchControls>) {
+    if !touch_controls.detected {
+        return;
+    }
+    let ctx = egui_context.ctx_mut();
+    let screen_rect = ctx.input().screen_rect();
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("touch-controls"),
+    ));
+    for zone in TouchZone::ALL {
+        let (from, to) = zone.x_fraction_range();
+        // Only the bottom of the zone is drawn, but the whole zone can be touched
+        let rect = egui::Rect::from_min_max(
+            egui::pos2(
+                screen_rect.left() + screen_rect.width() * from,
+                screen_rect.bottom() - screen_rect.height() * 0.25,
+            ),
+            egui::pos2(
+                screen_rect.left() + screen_rect.width() * to,
+                screen_rect.bottom(),
+            ),
+        )
+        .shrink(10.0);
+        let alpha = if touch_controls.pressed_zones.contains(&zone) {
+            96
+        } else {
+            32
+        };
+        painter.rect_filled(
+            rect,
+            10.0,
+            egui::Color32::from_rgba_unmultiplied(255, 255, 255, alpha),
+        );
+        painter.text(
+            rect.center(),
+            egui::Align2::CENTER_CENTER,
+            zone.label(),
+            egui::FontId::proportional(24.0),
+            egui::Color32::WHITE,
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use std::time::{Duration, Instant};
+
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::ElementState;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use jamming_chipper::global_types::{
+    AppState, DespawnWithLevel, GameplayClock, MenuState, PlayerControl, ScoreStatus,
+};
+use jamming_chipper::HeadlessGamePlugin;
+
+#[derive(Default)]
+struct EnteredStates(Vec<AppState>);
+
+#[derive(Default)]
+struct ScoreWhenLoading(Option<(Duration, u32, u32)>);
+
+struct Harness {
+    app: App,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let mut app = App::new();
+        app.add_plugin(HeadlessGamePlugin);
+        // Frame times in tests are whatever the machine gives us - so make every update a single
+        // gameplay step.
+        let mut clock = GameplayClock::default();
+        clock.one_step_per_frame = true;
+        app.insert_resource(clock);
+        app.init_resource::<EnteredStates>();
+        for state in [
+            AppState::Menu(MenuState::Main),
+            AppState::Menu(MenuState::Pause),
+            AppState::Menu(MenuState::GameOver),
+            AppState::ClearLevelAndThenLoad,
+            AppState::LoadLevel,
+            AppState::Game,
+        ] {
+            app.add_system_set(SystemSet::on_enter(state.clone()).with_system(
+                move |mut entered_states: ResMut<EnteredStates>| {
+                    entered_states.0.push(state.clone());
+                },
+            ));
+        }
+        app.init_resource::<ScoreWhenLoading>();
+        app.add_system_set(SystemSet::on_enter(AppState::LoadLevel).with_system(
+            |score_status: Res<ScoreStatus>, mut score_when_loading: ResMut<ScoreWhenLoading>| {
+                score_when_loading.0 = Some((
+                    score_status.time,
+                    score_status.logs_chipped,
+                    score_status.woodchips_cleared,
+                ));
+            },
+        ));
+        app.update();
+        Self { app }
+    }
+
+    fn step(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.app.update();
+        }
+    }
+
+    fn state(&self) -> AppState {
+        self.app
+            .world
+            .get_resource::<State<AppState>>()
+            .unwrap()
+            .current()
+            .clone()
+    }
+
+    fn set_state(&mut self, state: AppState) {
+        self.app
+            .world
+            .get_resource_mut::<State<AppState>>()
+            .unwrap()
+            .set(state)
+            .unwrap();
+    }
+
+    fn take_entered_states(&mut self) -> Vec<AppState> {
+        std::mem::take(
+            &mut self
+                .app
+                .world
+                .get_resource_mut::<EnteredStates>()
+                .unwrap()
+                .0,
+        )
+    }
+
+    fn send_key(&mut self, key_code: KeyCode, state: ElementState) {
+        self.app
+            .world
+            .get_resource_mut::<Events<KeyboardInput>>()
+            .unwrap()
+            .send(KeyboardInput {
+                scan_code: 0,
+                key_code: Some(key_code),
+                state,
+            });
+    }
+
+    /// Press and release a key, giving the input systems a few frames to see each.
+    fn tap_key(&mut self, key_code: KeyCode) {
+        self.send_key(key_code, ElementState::Pressed);
+        self.step(3);
+        self.send_key(key_code, ElementState::Released);
+        self.step(3);
+    }
+
+    /// Keep updating until the state machine reaches the given state. Used when the level asset
+    /// is still loading in the background, which takes an unknown number of frames.
+    fn step_until(&mut self, state: AppState) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while self.state() != state {
+            assert!(
+                Instant::now() < deadline,
+                "stuck in {:?} while waiting for {:?}",
+                self.state(),
+                state
+            );
+            self.app.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn start_game(&mut self) {
+        self.set_state(AppState::ClearLevelAndThenLoad);
+        self.step_until(AppState::Game);
+    }
+
+    fn throw_player_off_the_arena(&mut self) {
+        let mut query = self
+            .app
+            .world
+            .query_filtered::<&mut RigidBodyPositionComponent, With<PlayerControl>>();
+        for mut position in query.iter_mut(&mut self.app.world) {
+            position.position.translation.y = -10.0;
+        }
+    }
+}
+
+#[test]
+fn full_state_machine_flow() {
+    let mut harness = Harness::new();
+    assert_eq!(harness.state(), AppState::Menu(MenuState::Main));
+    assert_eq!(
+        harness.take_entered_states(),
+        [AppState::Menu(MenuState::Main)]
+    );
+
+    harness.start_game();
+    assert_eq!(
+        harness.take_entered_states(),
+        [
+            AppState::ClearLevelAndThenLoad,
+            AppState::LoadLevel,
+            AppState::Game,
+        ]
+    );
+
+    harness.tap_key(KeyCode::Escape);
+    assert_eq!(harness.state(), AppState::Menu(MenuState::Pause));
+
+    harness.tap_key(KeyCode::Escape);
+    assert_eq!(harness.state(), AppState::Game);
+
+    harness.throw_player_off_the_arena();
+    harness.step(3);
+    assert_eq!(harness.state(), AppState::Menu(MenuState::GameOver));
+    assert_eq!(
+        harness.take_entered_states(),
+        [
+            AppState::Menu(MenuState::Pause),
+            AppState::Game,
+            AppState::Menu(MenuState::GameOver),
+        ]
+    );
+}
+
+#[test]
+fn clear_and_load_despawns_level_entities() {
+    let mut harness = Harness::new();
+    harness.start_game();
+    harness.step(10);
+
+    let extra_entity = harness.app.world.spawn().insert(DespawnWithLevel).id();
+    let mut query = harness
+        .app
+        .world
+        .query_filtered::<Entity, With<DespawnWithLevel>>();
+    let level_entities: Vec<Entity> = query.iter(&harness.app.world).collect();
+    assert!(level_entities.contains(&extra_entity));
+    assert!(1 < level_entities.len());
+
+    harness.set_state(AppState::ClearLevelAndThenLoad);
+    harness.step(3);
+    assert_eq!(harness.state(), AppState::Game);
+    for entity in level_entities {
+        assert!(
+            harness.app.world.get_entity(entity).is_none(),
+            "{:?} survived the level reload",
+            entity
+        );
+    }
+}
+
+#[test]
+fn reset_score_zeroes_score_status() {
+    let mut harness = Harness::new();
+    harness.start_game();
+    harness.step(10);
+    {
+        let mut score_status = harness.app.world.get_resource_mut::<ScoreStatus>().unwrap();
+        assert!(!score_status.time.is_zero());
+        score_status.logs_chipped = 5;
+        score_status.woodchips_cleared = 7;
+    }
+
+    harness.set_state(AppState::ClearLevelAndThenLoad);
+    harness.step(3);
+    assert_eq!(
+        harness
+            .app
+            .world
+            .get_resource::<ScoreWhenLoading>()
+            .unwrap()
+            .0,
+        Some((Duration::ZERO, 0, 0))
+    );
+}
@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+
+use jamming_chipper::controls::{rebind, ControlAction};
+
+#[test]
+fn rebind_replaces_all_the_bindings_of_the_action() {
+    let mut bindings = vec![
+        (ControlAction::Jump, KeyCode::Up),
+        (ControlAction::Jump, KeyCode::W),
+        (ControlAction::Pause, KeyCode::Escape),
+    ];
+    assert_eq!(
+        rebind(&mut bindings, ControlAction::Jump, KeyCode::Space),
+        Ok(())
+    );
+    assert_eq!(
+        bindings,
+        vec![
+            (ControlAction::Pause, KeyCode::Escape),
+            (ControlAction::Jump, KeyCode::Space),
+        ]
+    );
+}
+
+#[test]
+fn rebind_rejects_an_input_bound_to_another_action() {
+    let mut bindings = vec![
+        (ControlAction::MoveLeft, KeyCode::A),
+        (ControlAction::Jump, KeyCode::W),
+    ];
+    assert_eq!(
+        rebind(&mut bindings, ControlAction::Jump, KeyCode::A),
+        Err(ControlAction::MoveLeft)
+    );
+    assert_eq!(
+        bindings,
+        vec![
+            (ControlAction::MoveLeft, KeyCode::A),
+            (ControlAction::Jump, KeyCode::W),
+        ]
+    );
+}
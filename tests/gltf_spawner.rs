@@ -0,0 +1,157 @@
+use std::time::{Duration, Instant};
+
+use bevy::asset::AssetServerSettings;
+use bevy::gltf::{Gltf, GltfPlugin};
+use bevy::prelude::*;
+use bevy::scene::ScenePlugin;
+use bevy_rapier2d::prelude::*;
+
+use jamming_chipper::gltf_spawner::{
+    outline_segments, resolve_collider_events, BodyIntersectionEvent, GltfSpawnerPlugin,
+    SpawnCollider,
+};
+
+#[test]
+fn quad_outline_has_four_edges() {
+    let vertices = [
+        point![0.0, 0.0],
+        point![1.0, 0.0],
+        point![1.0, 1.0],
+        point![0.0, 1.0],
+    ];
+    let outline = outline_segments(&vertices, &[[0, 1, 2], [0, 2, 3]]);
+    assert_eq!(outline, vec![[0, 1], [1, 2], [2, 3], [3, 0]]);
+}
+
+#[test]
+fn quad_outline_ignores_split_vertices() {
+    // Exporters duplicate the vertices of the diagonal when the triangles don't share normals or UVs
+    let vertices = [
+        point![0.0, 0.0],
+        point![1.0, 0.0],
+        point![1.0, 1.0],
+        point![0.0, 0.0],
+        point![1.0, 1.0],
+        point![0.0, 1.0],
+    ];
+    let outline = outline_segments(&vertices, &[[0, 1, 2], [3, 4, 5]]);
+    assert_eq!(outline, vec![[0, 1], [1, 2], [2, 5], [5, 0]]);
+}
+
+#[derive(Default)]
+struct Intersections(Vec<(Entity, Entity)>);
+
+#[test]
+fn events_from_every_part_of_a_multi_node_collider_name_the_body() {
+    let mut app = App::new();
+    app.insert_resource(AssetServerSettings {
+        asset_folder: "tests/assets".to_owned(),
+        watch_for_changes: false,
+    });
+    app.add_plugins(MinimalPlugins);
+    app.add_plugin(AssetPlugin::default());
+    app.add_plugin(ScenePlugin::default());
+    app.add_asset::<Mesh>();
+    app.add_asset::<Image>();
+    app.add_asset::<StandardMaterial>();
+    app.add_plugin(GltfPlugin::default());
+    app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default());
+    app.add_plugin(GltfSpawnerPlugin {
+        spawn_meshes: false,
+    });
+    app.add_system_to_stage(CoreStage::PostUpdate, resolve_collider_events);
+    app.init_resource::<Intersections>();
+    // The balls must stay in place until the model is loaded
+    app.world
+        .get_resource_mut::<RapierConfiguration>()
+        .unwrap()
+        .gravity = vector![0.0, 0.0];
+    app.add_system_to_stage(
+        CoreStage::Last,
+        |mut reader: EventReader<BodyIntersectionEvent>,
+         mut intersections: ResMut<Intersections>| {
+            for event in reader.iter() {
+                if event.intersecting {
+                    intersections.0.push((event.body1, event.body2));
+                }
+            }
+        },
+    );
+
+    // The collider node has no mesh. Its `Left` and `Right` children share their properties, and
+    // `Top` has a different friction - so it gets a separate collider on a child entity.
+    let gltf: Handle<Gltf> = app
+        .world
+        .get_resource::<AssetServer>()
+        .unwrap()
+        .load("models/multi-part-collider.glb");
+    let body = app
+        .world
+        .spawn()
+        .insert_bundle(RigidBodyBundle {
+            body_type: RigidBodyType::Static.into(),
+            ..Default::default()
+        })
+        .insert(SpawnCollider {
+            gltf,
+            node_name: "Collider",
+            ..Default::default()
+        })
+        .id();
+    let spawn_ball = |app: &mut App, x: f32, y: f32| {
+        app.world
+            .spawn()
+            .insert_bundle(RigidBodyBundle {
+                position: point![x, y].into(),
+                ..Default::default()
+            })
+            .insert_bundle(ColliderBundle {
+                shape: SharedShape::ball(0.25).into(),
+                ..Default::default()
+            })
+            .id()
+    };
+    let in_right = spawn_ball(&mut app, 2.0, 0.0);
+    let in_top = spawn_ball(&mut app, 0.0, 3.0);
+    // Between the parts - only hit if they are not placed by their node transforms
+    spawn_ball(&mut app, 0.0, 0.0);
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while app.world.get_resource::<Intersections>().unwrap().0.len() < 2 {
+        assert!(
+            Instant::now() < deadline,
+            "timed out waiting for the events"
+        );
+        app.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    for _ in 0..10 {
+        app.update();
+    }
+
+    let mut intersections: Vec<Entity> = app
+        .world
+        .get_resource::<Intersections>()
+        .unwrap()
+        .0
+        .iter()
+        .map(|&(body1, body2)| {
+            assert!(
+                body1 == body || body2 == body,
+                "event between {:?} and {:?} does not name the body {:?}",
+                body1,
+                body2,
+                body
+            );
+            if body1 == body {
+                body2
+            } else {
+                body1
+            }
+        })
+        .collect();
+    intersections.sort();
+    let mut expected = vec![in_right, in_top];
+    expected.sort();
+    assert_eq!(intersections, expected);
+}
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use jamming_chipper::global_types::{ScoreFormula, ScoreStatus};
+
+#[test]
+fn quick_clears_grow_the_combo() {
+    let formula = ScoreFormula::default();
+    let mut score_status = ScoreStatus::default();
+    score_status.clear_woodchip(&formula, Duration::from_secs(1));
+    score_status.clear_woodchip(&formula, Duration::from_secs(2));
+    score_status.clear_woodchip(&formula, Duration::from_secs(3));
+    assert_eq!(score_status.combo, 2);
+    assert_eq!(score_status.woodchips_cleared, 3);
+}
+
+#[test]
+fn clear_after_the_combo_window_resets_the_combo() {
+    let formula = ScoreFormula::default();
+    let mut score_status = ScoreStatus::default();
+    score_status.clear_woodchip(&formula, Duration::from_secs(1));
+    score_status.clear_woodchip(&formula, Duration::from_secs(2));
+    assert_eq!(score_status.combo, 1);
+    score_status.clear_woodchip(&formula, Duration::from_secs(2) + formula.combo_window * 2);
+    assert_eq!(score_status.combo, 0);
+    assert_eq!(score_status.combo_multiplier(&formula), 1.0);
+}